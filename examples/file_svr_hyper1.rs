@@ -0,0 +1,27 @@
+use std::net::SocketAddr;
+
+use blockless_hyper_file::FileService;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder as ConnBuilder,
+};
+use tokio::{net::TcpListener, runtime::Builder};
+
+fn main() {
+    let rt = Builder::new_multi_thread().enable_io().build().unwrap();
+    let addr: SocketAddr = ([127, 0, 0, 1], 9088).into();
+    rt.block_on(async move {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let service = FileService::new(".");
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service.clone();
+            tokio::spawn(async move {
+                let builder = ConnBuilder::new(TokioExecutor::new());
+                let _ = builder
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
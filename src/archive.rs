@@ -0,0 +1,848 @@
+//! The streamed zip and tar archives of the directory, the files are read one by one when the
+//! body is polled and never staged on the disk.
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    future::Future,
+    io::{self, Write},
+    mem,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{
+    write::{DeflateEncoder, GzEncoder},
+    Compression, Crc,
+};
+use futures_util::StreamExt;
+use hyper::{body::Bytes, header, Method, Request, Response, StatusCode};
+
+use crate::{
+    body::{ArchiveStream, Body, FileBytesStream},
+    config::Config,
+    disposition::content_disposition,
+    error::Error,
+    executor::BlockingFuture,
+    file::{FileReaderOpener, FileWithMeta},
+    request_resolve::{request_path, strip_host},
+};
+
+// the directories deeper than the limit are archived without the members, e.g. the symlink loop.
+const MAX_DEPTH: usize = 32;
+// the zip64 is used for the big file, the deflated file may be a little bigger.
+const ZIP64_THRESHOLD: u64 = 0xF000_0000;
+const ZIP32_MAX: u64 = 0xFFFF_FFFF;
+const TAR_BLOCK: usize = 512;
+
+/// The format of the archive, selected by the `archive` query, e.g. `?archive=zip`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    /// the format of the `archive` parameter of the query.
+    pub(crate) fn from_query(query: &str) -> Option<Self> {
+        query
+            .split('&')
+            .filter_map(|p| p.strip_prefix("archive="))
+            .find_map(|v| match v {
+                "zip" => Some(Format::Zip),
+                "tar" => Some(Format::Tar),
+                "tar.gz" | "tgz" => Some(Format::TarGz),
+                _ => None,
+            })
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::Tar => "tar",
+            Format::TarGz => "tar.gz",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Zip => "application/zip",
+            Format::Tar => "application/x-tar",
+            Format::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// the file or the directory in the archive.
+#[derive(Debug)]
+struct Entry {
+    // the path for the opener.
+    path: PathBuf,
+    // the name in the archive, the directory ends with the `/`.
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// the canonical local root of the path, the first component is the host of the virtual host
+/// opener. none if the opener has no local files.
+fn local_root_blocking<O: FileReaderOpener>(opener: &O, path: &Path) -> Option<PathBuf> {
+    let root: PathBuf = match opener.is_virtual_host() {
+        true => path.iter().take(1).collect(),
+        false => PathBuf::new(),
+    };
+    opener.local_path(&root)?.canonicalize().ok()
+}
+
+/// the children which are the symbolic links out of the root, should be called in the blocking
+/// thread. the broken link is out of the root.
+fn escaped_blocking(root: &Path, dir: &Path, names: Vec<String>) -> HashSet<String> {
+    names
+        .into_iter()
+        .filter(|name| {
+            let path = dir.join(name);
+            let is_link = fs::symlink_metadata(&path)
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false);
+            is_link
+                && !path
+                    .canonicalize()
+                    .map(|p| p.starts_with(root))
+                    .unwrap_or(false)
+        })
+        .collect()
+}
+
+/// walk the directory, the hidden files and the links out of the root are skipped. none if the
+/// entries are more than the limit.
+async fn walk<O: FileReaderOpener>(
+    opener: &Arc<O>,
+    config: &Config,
+    root: PathBuf,
+    base: String,
+    modified: Option<SystemTime>,
+) -> io::Result<Option<Vec<Entry>>> {
+    let mut entries = vec![Entry {
+        path: root.clone(),
+        name: format!("{base}/"),
+        is_dir: true,
+        size: 0,
+        modified,
+    }];
+    let executor = opener.blocking_executor();
+    let local_root = match config.archive_follow_links {
+        true => None,
+        false => {
+            let (opener, root) = (opener.clone(), root.clone());
+            BlockingFuture::spawn(&*executor, move || Ok(local_root_blocking(&*opener, &root)))
+                .await?
+        }
+    };
+    let mut dirs = VecDeque::from([(root, format!("{base}/"), 0)]);
+    while let Some((dir, prefix, depth)) = dirs.pop_front() {
+        let mut children = opener.read_dir(&dir).await?;
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        let escaped = match local_root {
+            Some(ref local_root) => {
+                let (opener, dir, local_root) = (opener.clone(), dir.clone(), local_root.clone());
+                let names = children.iter().map(|c| c.name.clone()).collect();
+                BlockingFuture::spawn(&*executor, move || {
+                    Ok(match opener.local_path(&dir) {
+                        Some(local_dir) => escaped_blocking(&local_root, &local_dir, names),
+                        None => HashSet::new(),
+                    })
+                })
+                .await?
+            }
+            None => HashSet::new(),
+        };
+        for child in children {
+            if config.is_hidden(&strip_host(&**opener, &dir).join(&child.name)) {
+                continue;
+            }
+            if escaped.contains(&child.name) {
+                continue;
+            }
+            if entries.len() >= config.max_archive_entries {
+                return Ok(None);
+            }
+            let path = dir.join(&child.name);
+            let mut name = format!("{prefix}{}", child.name);
+            if child.is_dir {
+                name.push('/');
+                if depth + 1 < MAX_DEPTH {
+                    dirs.push_back((path.clone(), name.clone(), depth + 1));
+                }
+            }
+            entries.push(Entry {
+                path,
+                name,
+                is_dir: child.is_dir,
+                size: if child.is_dir { 0 } else { child.size },
+                modified: child.modified,
+            });
+        }
+    }
+    Ok(Some(entries))
+}
+
+fn unix_secs(modified: Option<SystemTime>) -> u64 {
+    modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// the ms-dos time and date of the zip, the time before 1980 is clamped.
+fn dos_time(modified: Option<SystemTime>) -> (u16, u16) {
+    let secs = unix_secs(modified);
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // the civil date of the days since the epoch.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let year = year.min(2107);
+    let time = (rem / 3600) << 11 | (rem % 3600 / 60) << 5 | (rem % 60 / 2);
+    let date = (year - 1980) << 9 | month << 5 | day;
+    (time as u16, date as u16)
+}
+
+/// the central record of the zip entry.
+struct ZipEntry {
+    name: String,
+    is_dir: bool,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    size: u64,
+    compressed: u64,
+    offset: u64,
+    zip64: bool,
+}
+
+/// The zip writer, the crc and the sizes are written in the data descriptor after the data.
+#[derive(Default)]
+struct Zip {
+    entries: Vec<ZipEntry>,
+    offset: u64,
+}
+
+impl Zip {
+    /// the local header of the entry.
+    fn start(&mut self, entry: &Entry, deflate: bool) -> Vec<u8> {
+        let (time, date) = dos_time(entry.modified);
+        let zip64 = entry.size >= ZIP64_THRESHOLD;
+        let e = ZipEntry {
+            name: entry.name.clone(),
+            is_dir: entry.is_dir,
+            method: if deflate && !entry.is_dir { 8 } else { 0 },
+            time,
+            date,
+            crc: 0,
+            size: entry.size,
+            compressed: 0,
+            offset: self.offset,
+            zip64,
+        };
+        let mut buf = Vec::with_capacity(30 + e.name.len() + 20);
+        buf.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        buf.extend_from_slice(&(if zip64 { 45u16 } else { 20 }).to_le_bytes());
+        // the data descriptor and the utf-8 name.
+        buf.extend_from_slice(&0x0808u16.to_le_bytes());
+        buf.extend_from_slice(&e.method.to_le_bytes());
+        buf.extend_from_slice(&time.to_le_bytes());
+        buf.extend_from_slice(&date.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        let size = if zip64 { u32::MAX } else { 0 };
+        buf.extend_from_slice(&size.to_le_bytes());
+        buf.extend_from_slice(&size.to_le_bytes());
+        buf.extend_from_slice(&(e.name.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&(if zip64 { 20u16 } else { 0 }).to_le_bytes());
+        buf.extend_from_slice(e.name.as_bytes());
+        if zip64 {
+            buf.extend_from_slice(&1u16.to_le_bytes());
+            buf.extend_from_slice(&16u16.to_le_bytes());
+            buf.extend_from_slice(&[0; 16]);
+        }
+        self.offset += buf.len() as u64;
+        self.entries.push(e);
+        buf
+    }
+
+    /// the data descriptor of the current entry.
+    fn finish(&mut self, crc: u32, compressed: u64) -> Vec<u8> {
+        let e = self.entries.last_mut().expect("the entry is started");
+        e.crc = crc;
+        e.compressed = compressed;
+        let mut buf = Vec::with_capacity(24);
+        buf.extend_from_slice(&0x0807_4b50u32.to_le_bytes());
+        buf.extend_from_slice(&crc.to_le_bytes());
+        if e.zip64 {
+            buf.extend_from_slice(&compressed.to_le_bytes());
+            buf.extend_from_slice(&e.size.to_le_bytes());
+        } else {
+            buf.extend_from_slice(&(compressed as u32).to_le_bytes());
+            buf.extend_from_slice(&(e.size as u32).to_le_bytes());
+        }
+        self.offset += compressed + buf.len() as u64;
+        buf
+    }
+
+    /// the central directory and the end records, the zip64 records for the big archive.
+    fn end(&mut self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let cd_offset = self.offset;
+        for e in self.entries.iter() {
+            let mut extra = Vec::new();
+            if e.zip64 {
+                extra.extend_from_slice(&e.size.to_le_bytes());
+                extra.extend_from_slice(&e.compressed.to_le_bytes());
+            }
+            if e.offset >= ZIP32_MAX {
+                extra.extend_from_slice(&e.offset.to_le_bytes());
+            }
+            let needed = if extra.is_empty() { 20u16 } else { 45 };
+            buf.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            // made by the unix, the external attributes are the modes.
+            buf.extend_from_slice(&(0x0300 | 45u16).to_le_bytes());
+            buf.extend_from_slice(&needed.to_le_bytes());
+            buf.extend_from_slice(&0x0808u16.to_le_bytes());
+            buf.extend_from_slice(&e.method.to_le_bytes());
+            buf.extend_from_slice(&e.time.to_le_bytes());
+            buf.extend_from_slice(&e.date.to_le_bytes());
+            buf.extend_from_slice(&e.crc.to_le_bytes());
+            let (compressed, size) = match e.zip64 {
+                true => (u32::MAX, u32::MAX),
+                false => (e.compressed as u32, e.size as u32),
+            };
+            buf.extend_from_slice(&compressed.to_le_bytes());
+            buf.extend_from_slice(&size.to_le_bytes());
+            buf.extend_from_slice(&(e.name.len() as u16).to_le_bytes());
+            let extra_len = if extra.is_empty() { 0 } else { extra.len() + 4 };
+            buf.extend_from_slice(&(extra_len as u16).to_le_bytes());
+            buf.extend_from_slice(&[0; 6]);
+            let attrs: u32 = match e.is_dir {
+                true => (0o40755 << 16) | 0x10,
+                false => 0o100644 << 16,
+            };
+            buf.extend_from_slice(&attrs.to_le_bytes());
+            buf.extend_from_slice(&(e.offset.min(ZIP32_MAX) as u32).to_le_bytes());
+            buf.extend_from_slice(e.name.as_bytes());
+            if !extra.is_empty() {
+                buf.extend_from_slice(&1u16.to_le_bytes());
+                buf.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&extra);
+            }
+        }
+        let cd_size = buf.len() as u64;
+        let count = self.entries.len() as u64;
+        if count >= 0xFFFF || cd_offset >= ZIP32_MAX || cd_size >= ZIP32_MAX {
+            let eocd64_offset = cd_offset + cd_size;
+            buf.extend_from_slice(&0x0606_4b50u32.to_le_bytes());
+            buf.extend_from_slice(&44u64.to_le_bytes());
+            buf.extend_from_slice(&(0x0300 | 45u16).to_le_bytes());
+            buf.extend_from_slice(&45u16.to_le_bytes());
+            buf.extend_from_slice(&[0; 8]);
+            buf.extend_from_slice(&count.to_le_bytes());
+            buf.extend_from_slice(&count.to_le_bytes());
+            buf.extend_from_slice(&cd_size.to_le_bytes());
+            buf.extend_from_slice(&cd_offset.to_le_bytes());
+            buf.extend_from_slice(&0x0706_4b50u32.to_le_bytes());
+            buf.extend_from_slice(&0u32.to_le_bytes());
+            buf.extend_from_slice(&eocd64_offset.to_le_bytes());
+            buf.extend_from_slice(&1u32.to_le_bytes());
+        }
+        buf.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        buf.extend_from_slice(&[0; 4]);
+        let count = count.min(0xFFFF) as u16;
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(&(cd_size.min(ZIP32_MAX) as u32).to_le_bytes());
+        buf.extend_from_slice(&(cd_offset.min(ZIP32_MAX) as u32).to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf
+    }
+}
+
+/// the octal field of the tar header, the big number is in the base-256 of the gnu tar.
+fn tar_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        let octal = format!("{value:0digits$o}");
+        field[..digits].copy_from_slice(octal.as_bytes());
+        field[digits] = 0;
+    } else {
+        field.fill(0);
+        let bytes = value.to_be_bytes();
+        let n = field.len();
+        field[n - 8..].copy_from_slice(&bytes);
+        field[0] = 0x80;
+    }
+}
+
+fn tar_block(
+    name: &[u8],
+    prefix: &[u8],
+    typeflag: u8,
+    size: u64,
+    mtime: u64,
+    gnu: bool,
+) -> Vec<u8> {
+    let mut block = vec![0u8; TAR_BLOCK];
+    block[..name.len()].copy_from_slice(name);
+    let mode: u64 = if typeflag == b'5' { 0o755 } else { 0o644 };
+    tar_number(&mut block[100..108], mode);
+    tar_number(&mut block[108..116], 0);
+    tar_number(&mut block[116..124], 0);
+    tar_number(&mut block[124..136], size);
+    tar_number(&mut block[136..148], mtime);
+    block[156] = typeflag;
+    let magic: &[u8; 8] = if gnu { b"ustar  \0" } else { b"ustar\x0000" };
+    block[257..265].copy_from_slice(magic);
+    block[345..345 + prefix.len()].copy_from_slice(prefix);
+    // the checksum is counted with the spaces in the field.
+    block[148..156].fill(b' ');
+    let sum: u32 = block.iter().map(|b| u32::from(*b)).sum();
+    let sum = format!("{sum:06o}\0 ");
+    block[148..156].copy_from_slice(sum.as_bytes());
+    block
+}
+
+/// the tar header of the entry, the long name is in the gnu long link.
+fn tar_header(entry: &Entry) -> Vec<u8> {
+    let name = entry.name.as_bytes();
+    let typeflag = if entry.is_dir { b'5' } else { b'0' };
+    let mtime = unix_secs(entry.modified);
+    if name.len() <= 100 {
+        return tar_block(name, b"", typeflag, entry.size, mtime, false);
+    }
+    // the ustar splits the name at the `/`.
+    let split = (0..name.len() - 1)
+        .filter(|i| name[*i] == b'/')
+        .find(|i| *i <= 155 && name.len() - i - 1 <= 100);
+    if let Some(i) = split {
+        return tar_block(
+            &name[i + 1..],
+            &name[..i],
+            typeflag,
+            entry.size,
+            mtime,
+            false,
+        );
+    }
+    let mut buf = tar_block(b"././@LongLink", b"", b'L', name.len() as u64 + 1, 0, true);
+    buf.extend_from_slice(name);
+    buf.resize(buf.len() + tar_padding(name.len() as u64 + 1) + 1, 0);
+    buf.extend(tar_block(
+        &name[..100],
+        b"",
+        typeflag,
+        entry.size,
+        mtime,
+        true,
+    ));
+    buf
+}
+
+fn tar_padding(size: u64) -> usize {
+    (TAR_BLOCK - (size % TAR_BLOCK as u64) as usize) % TAR_BLOCK
+}
+
+/// the length of the archive without the compression, none for the compressed archive.
+fn content_length(format: Format, store_only: bool, entries: &[Entry]) -> Option<u64> {
+    match format {
+        Format::Zip if store_only => {
+            let mut zip = Zip::default();
+            let mut length = 0;
+            for entry in entries {
+                length += zip.start(entry, false).len() as u64 + entry.size;
+                length += zip.finish(0, entry.size).len() as u64;
+            }
+            Some(length + zip.end().len() as u64)
+        }
+        Format::Tar => {
+            let length = entries
+                .iter()
+                .map(|e| tar_header(e).len() as u64 + e.size + tar_padding(e.size) as u64)
+                .sum::<u64>();
+            Some(length + 2 * TAR_BLOCK as u64)
+        }
+        _ => None,
+    }
+}
+
+/// the file in the archive is reading, the directory and the empty file have no stream.
+struct Current {
+    stream: Option<FileBytesStream>,
+    entry: Entry,
+    read: u64,
+    crc: Crc,
+    deflate: Option<DeflateEncoder<Vec<u8>>>,
+    compressed: u64,
+}
+
+/// The writer of the archive, the chunks are produced one by one.
+struct ArchiveWriter<O> {
+    opener: Arc<O>,
+    format: Format,
+    store_only: bool,
+    entries: std::vec::IntoIter<Entry>,
+    zip: Zip,
+    gzip: Option<GzEncoder<Vec<u8>>>,
+    current: Option<Current>,
+    done: bool,
+}
+
+impl<O: FileReaderOpener> ArchiveWriter<O> {
+    /// the output of the archive, the tar is compressed by the gzip.
+    fn emit(&mut self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self.gzip {
+            Some(ref mut gzip) => {
+                gzip.write_all(&data)?;
+                Ok(mem::take(gzip.get_mut()))
+            }
+            None => Ok(data),
+        }
+    }
+
+    /// the header of the entry, the file is opened for the data.
+    async fn start(&mut self, entry: Entry) -> io::Result<Vec<u8>> {
+        let (header, deflate) = match self.format {
+            Format::Zip => {
+                let deflate = !self.store_only && !entry.is_dir;
+                let header = self.zip.start(&entry, deflate);
+                (
+                    header,
+                    deflate.then(|| DeflateEncoder::new(Vec::new(), Compression::default())),
+                )
+            }
+            Format::Tar | Format::TarGz => (tar_header(&entry), None),
+        };
+        let stream = match entry.is_dir || entry.size == 0 {
+            true => None,
+            false => {
+                let file: FileWithMeta = self.opener.open(&entry.path).await?.into();
+                Some(FileBytesStream::new_with_limited(file.reader, entry.size))
+            }
+        };
+        self.current = Some(Current {
+            stream,
+            entry,
+            read: 0,
+            crc: Crc::new(),
+            deflate,
+            compressed: 0,
+        });
+        Ok(header)
+    }
+
+    /// the data of the file, the data is deflated for the zip.
+    fn data(current: &mut Current, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        current.read += bytes.len() as u64;
+        current.crc.update(bytes);
+        match current.deflate {
+            Some(ref mut deflate) => {
+                deflate.write_all(bytes)?;
+                let out = mem::take(deflate.get_mut());
+                current.compressed += out.len() as u64;
+                Ok(out)
+            }
+            None => Ok(bytes.to_vec()),
+        }
+    }
+
+    /// the trailer of the file, e.g. the data descriptor or the padding.
+    fn finish(&mut self, current: Current) -> io::Result<Vec<u8>> {
+        // the length of the archive is computed with the size of the listing.
+        if current.read != current.entry.size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the file is changed while archiving",
+            ));
+        }
+        match self.format {
+            Format::Zip => {
+                let (mut buf, compressed) = match current.deflate {
+                    Some(deflate) => {
+                        let buf = deflate.finish()?;
+                        let compressed = current.compressed + buf.len() as u64;
+                        (buf, compressed)
+                    }
+                    None => (Vec::new(), current.read),
+                };
+                buf.extend(self.zip.finish(current.crc.sum(), compressed));
+                Ok(buf)
+            }
+            Format::Tar | Format::TarGz => Ok(vec![0; tar_padding(current.read)]),
+        }
+    }
+
+    /// the central directory of the zip or the end blocks of the tar.
+    fn end(&mut self) -> io::Result<Vec<u8>> {
+        let out = match self.format {
+            Format::Zip => self.zip.end(),
+            Format::Tar | Format::TarGz => vec![0; 2 * TAR_BLOCK],
+        };
+        let mut out = self.emit(out)?;
+        if let Some(gzip) = self.gzip.take() {
+            out.extend(gzip.finish()?);
+        }
+        Ok(out)
+    }
+
+    /// the next chunk of the archive, the empty output of the compression is skipped.
+    async fn next_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        while !self.done {
+            let out = match self.current {
+                Some(ref mut current) => {
+                    let bytes = match current.stream {
+                        Some(ref mut stream) => stream.next().await.transpose()?,
+                        None => None,
+                    };
+                    match bytes {
+                        Some(bytes) => Self::data(current, &bytes)?,
+                        None => {
+                            let current = self.current.take().expect("the file is reading");
+                            self.finish(current)?
+                        }
+                    }
+                }
+                None => match self.entries.next() {
+                    Some(entry) => self.start(entry).await?,
+                    None => {
+                        self.done = true;
+                        let out = self.end()?;
+                        return Ok(Some(out.into()));
+                    }
+                },
+            };
+            let out = self.emit(out)?;
+            if !out.is_empty() {
+                return Ok(Some(out.into()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The future of the archive response, none if the path is not the directory.
+pub(crate) type ArchiveFuture =
+    Pin<Box<dyn Future<Output = Result<Option<Response<Body>>, Error>> + Send>>;
+
+/// respond the archive of the directory, the request is not borrowed by the future. the
+/// resolver responds the request which is not the directory.
+pub(crate) fn respond<O, B>(
+    opener: &Arc<O>,
+    config: &Arc<Config>,
+    request: &Request<B>,
+    format: Format,
+) -> ArchiveFuture
+where
+    O: FileReaderOpener,
+{
+    let path = match request_path(&**opener, config, request) {
+        Some(path) => path,
+        None => return Box::pin(std::future::ready(Ok(None))),
+    };
+    let is_head = request.method() == Method::HEAD;
+    Box::pin(archive(
+        opener.clone(),
+        config.clone(),
+        path,
+        is_head,
+        format,
+    ))
+}
+
+async fn archive<O: FileReaderOpener>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    path: PathBuf,
+    is_head: bool,
+    format: Format,
+) -> Result<Option<Response<Body>>, Error> {
+    let dir: FileWithMeta = match opener.open(&path).await {
+        Ok(dir) => dir.into(),
+        Err(_) => return Ok(None),
+    };
+    if !dir.is_dir {
+        return Ok(None);
+    }
+    let base = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => "archive".to_string(),
+    };
+    let entries = match walk(&opener, &config, path, base.clone(), dir.modified).await {
+        Ok(Some(entries)) => entries,
+        Ok(None) => {
+            return Ok(Some(
+                Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body(Body::Empty)?,
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let store_only = config.archive_store_only;
+    let filename = format!("{base}.{}", format.extension());
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(true, &filename),
+        )
+        .header(header::ACCEPT_RANGES, "none");
+    if let Some(length) = content_length(format, store_only, &entries) {
+        builder = builder.header(header::CONTENT_LENGTH, length);
+    }
+    if is_head {
+        return Ok(Some(builder.body(Body::Empty)?));
+    }
+    let writer = ArchiveWriter {
+        opener,
+        format,
+        store_only,
+        entries: entries.into_iter(),
+        zip: Zip::default(),
+        gzip: (format == Format::TarGz).then(|| GzEncoder::new(Vec::new(), Compression::default())),
+        current: None,
+        done: false,
+    };
+    let stream = futures_util::stream::try_unfold(writer, |mut writer| async move {
+        let chunk = writer.next_chunk().await?;
+        Ok(chunk.map(|chunk| (chunk, writer)))
+    });
+    Ok(Some(
+        builder.body(Body::Archive(ArchiveStream::new(stream)))?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            Format::from_query("a=1&archive=tar.gz"),
+            Some(Format::TarGz)
+        );
+        assert_eq!(Format::from_query("archive=rar"), None);
+        assert_eq!(dos_time(None), (0, (1 << 5) | 1));
+        // 2024-02-29 12:34:56 UTC.
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1709210096);
+        assert_eq!(
+            dos_time(Some(time)),
+            (12 << 11 | 34 << 5 | 28, 44 << 9 | 2 << 5 | 29)
+        );
+        let mut field = [0u8; 12];
+        tar_number(&mut field, 8);
+        assert_eq!(&field, b"00000000010\0");
+        tar_number(&mut field, 1 << 40);
+        assert_eq!(field[0], 0x80);
+        let entry = |name: &str| Entry {
+            path: PathBuf::new(),
+            name: name.to_string(),
+            is_dir: false,
+            size: 1,
+            modified: None,
+        };
+        let long = format!("{}/{}", "a".repeat(120), "b".repeat(90));
+        let header = tar_header(&entry(&long));
+        assert_eq!(header.len(), TAR_BLOCK);
+        assert_eq!(&header[345..465], "a".repeat(120).as_bytes());
+        assert_eq!(tar_header(&entry(&"c".repeat(300))).len(), 3 * TAR_BLOCK);
+    }
+
+    #[test]
+    fn test_archive() {
+        let root = TempRoot::new("archive");
+        root.write("docs/a.txt", "hello ".repeat(1000))
+            .write("docs/sub/b.txt", "b")
+            .write("docs/empty.txt", "")
+            .write("docs/.secret", "s");
+        runtime().block_on(async {
+            for store_only in [true, false] {
+                let mut svc = FileServiceBuilder::new(root.root())
+                    .archive(true)
+                    .archive_store_only(store_only)
+                    .hidden_files(false)
+                    .build();
+                for format in ["zip", "tar", "tar.gz"] {
+                    let uri = format!("/docs/?archive={format}");
+                    let resp = call(&mut svc, request("GET", &uri, &[], "")).await;
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    let disposition = format!("attachment; filename=\"docs.{format}\"");
+                    assert_eq!(resp.headers()[header::CONTENT_DISPOSITION], disposition);
+                    let length = resp.headers().get(header::CONTENT_LENGTH).cloned();
+                    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+                    match length {
+                        Some(length) => assert_eq!(length, body.len().to_string()),
+                        None => assert!(format == "tar.gz" || !store_only),
+                    }
+                    if format == "zip" {
+                        // the end of the central directory with the 5 entries.
+                        let end = &body[body.len() - 22..];
+                        assert_eq!(&end[..4], b"PK\x05\x06");
+                        assert_eq!(&end[10..12], &5u16.to_le_bytes());
+                    }
+                }
+            }
+            let mut svc = FileServiceBuilder::new(root.root())
+                .archive(true)
+                .max_archive_entries(3)
+                .build();
+            let resp = call(&mut svc, request("GET", "/docs/?archive=zip", &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_archive_links() {
+        let outside = TempRoot::new("archive-outside");
+        outside.write("secret.txt", "secret");
+        let root = TempRoot::new("archive-links");
+        root.write("docs/sub/b.txt", "b");
+        let link = |target: &Path, name: &str| {
+            std::os::unix::fs::symlink(target, root.join(name)).unwrap();
+        };
+        link(&root.join("docs/sub"), "docs/in");
+        link(&outside.join("secret.txt"), "docs/out.txt");
+        link(outside.path(), "docs/outdir");
+        runtime().block_on(async {
+            for follow_links in [false, true] {
+                let mut svc = FileServiceBuilder::new(root.root())
+                    .archive(true)
+                    .archive_follow_links(follow_links)
+                    .build();
+                let resp = call(&mut svc, request("GET", "/docs/?archive=tar", &[], "")).await;
+                assert_eq!(resp.status(), StatusCode::OK);
+                let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+                let contains = |name: &str| body.windows(name.len()).any(|w| w == name.as_bytes());
+                assert!(contains("docs/in/b.txt"));
+                assert_eq!(contains("docs/out.txt"), follow_links);
+                assert_eq!(contains("docs/outdir/secret.txt"), follow_links);
+            }
+        });
+    }
+}
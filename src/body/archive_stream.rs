@@ -0,0 +1,24 @@
+use futures_util::Stream;
+use hyper::body::Bytes;
+use std::{
+    io::Result,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The stream of the generated archive, the files are read when the body is polled.
+pub struct ArchiveStream(Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>);
+
+impl ArchiveStream {
+    pub(crate) fn new(stream: impl Stream<Item = Result<Bytes>> + Send + 'static) -> Self {
+        Self(Box::pin(stream))
+    }
+}
+
+impl Stream for ArchiveStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
@@ -6,9 +6,9 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::file::{FileReader, TokioFileReader};
+use crate::file::{BoxFileReader, FileReader};
 
-pub struct FileBytesStream<T = TokioFileReader> {
+pub struct FileBytesStream<T = BoxFileReader> {
     pub reader: T,
     pub(crate) remaining: u64,
 }
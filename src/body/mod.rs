@@ -7,19 +7,28 @@ use std::{
     task::{Context, Poll},
 };
 
+#[cfg(feature = "archive")]
+pub use archive_stream::ArchiveStream;
 pub use bytes_stream::FileBytesStream;
 pub use range_bytes_stream::MultiRangeBytesStream;
 pub use range_bytes_stream::RangeBytesStream;
 
+#[cfg(feature = "archive")]
+mod archive_stream;
 mod bytes_stream;
 mod chunked_bytes_stream;
 mod range_bytes_stream;
 
 pub enum Body {
     Empty,
+    // the generated content, e.g. the directory listing.
+    Bytes(Bytes),
     Full(FileBytesStream),
     RangeBytesStream(RangeBytesStream),
     MultiRangeBytesStream(MultiRangeBytesStream),
+    // the streamed archive of the directory.
+    #[cfg(feature = "archive")]
+    Archive(ArchiveStream),
 }
 
 impl hyper::body::HttpBody for Body {
@@ -35,7 +44,10 @@ impl hyper::body::HttpBody for Body {
             Body::MultiRangeBytesStream(ref mut mr) => Pin::new(mr).poll_next(cx),
             Body::RangeBytesStream(ref mut r) => Pin::new(r).poll_next(cx),
             Body::Full(ref mut r) => Pin::new(r).poll_next(cx),
-            Body::Empty => Poll::Ready(None),
+            #[cfg(feature = "archive")]
+            Body::Archive(ref mut a) => Pin::new(a).poll_next(cx),
+            Body::Bytes(ref mut b) if !b.is_empty() => Poll::Ready(Some(Ok(std::mem::take(b)))),
+            Body::Bytes(_) | Body::Empty => Poll::Ready(None),
         }
     }
 
@@ -2,14 +2,12 @@ use std::fmt::Write;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use crate::file::{BoxFileReader, FileReader};
+use crate::range::HttpRange;
 use futures_util::Stream;
 use hyper::body::Bytes;
 use std::io::{Result, SeekFrom};
 use std::vec;
-use tokio::io::AsyncSeek;
-
-use crate::file::TokioFileReader;
-use crate::range::HttpRange;
 
 use super::bytes_stream::FileBytesStream;
 
@@ -27,14 +25,14 @@ pub struct RangeBytesStream {
 }
 
 impl RangeBytesStream {
-    pub fn new_with_range(reader: TokioFileReader, range: &HttpRange) -> RangeBytesStream {
+    pub fn new_with_range(reader: BoxFileReader, range: &HttpRange) -> RangeBytesStream {
         Self {
             stream: FileBytesStream::new_with_limited(reader, range.length),
             start_pos: range.start,
             state: RangeState::Inital,
         }
     }
-    pub fn new(reader: TokioFileReader) -> RangeBytesStream {
+    pub fn new(reader: BoxFileReader) -> RangeBytesStream {
         RangeBytesStream {
             stream: FileBytesStream::new_with_limited(reader, 0),
             state: RangeState::Inital,
@@ -84,7 +82,7 @@ pub struct MultiRangeBytesStream {
 
 impl MultiRangeBytesStream {
     pub fn new(
-        reader: TokioFileReader,
+        reader: BoxFileReader,
         ranges: Vec<HttpRange>,
         boundary: String,
         file_size: u64,
@@ -144,7 +142,8 @@ impl MultiRangeBytesStream {
         write!(
             &mut buf,
             "--{boundary}\r\nContent-Range: bytes {}-{}/{file_size}\r\n",
-            range.start, range.length,
+            range.start,
+            range.start + range.length - 1,
         )
         .expect("buf write error");
 
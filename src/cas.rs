@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Error, ErrorKind, Read, Result, Seek},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use sha2::Digest;
+
+use crate::{
+    executor::{default_executor, BlockingExecutor, SharedExecutor},
+    file::{FileReaderOpener, FileWithMeta, FileWithMetaFuture},
+};
+
+const HASH_BUF_SIZE: usize = 64 * 1024;
+const DEFAULT_SHARD_LEVELS: usize = 2;
+
+/// The hash algorithms of the content addressed store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// the name used in the request path and the store layout.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// the length of the digest in hex.
+    fn hex_len(&self) -> usize {
+        // both the sha256 and the blake3 digest are 32 bytes.
+        64
+    }
+
+    /// compute the hex digest of the reader content.
+    fn hex_digest(&self, reader: &mut impl Read) -> Result<String> {
+        let mut buf = vec![0u8; HASH_BUF_SIZE];
+        let digest: Vec<u8> = match *self {
+            HashAlgorithm::Sha256 => {
+                let mut hasher = sha2::Sha256::new();
+                loop {
+                    let n = reader.read(&mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                }
+                hasher.finalize().to_vec()
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                loop {
+                    let n = reader.read(&mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                }
+                hasher.finalize().as_bytes().to_vec()
+            }
+        };
+        Ok(to_hex(&digest))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    const HEX_CHRS: &[u8] = b"0123456789abcdef";
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push(HEX_CHRS[(b >> 4) as usize] as char);
+        s.push(HEX_CHRS[(b & 0xf) as usize] as char);
+    }
+    s
+}
+
+/// The blob address parsed from the request path, e.g. `sha256/<hex>`.
+#[derive(Debug, PartialEq)]
+struct BlobAddr {
+    algorithm: HashAlgorithm,
+    // the lowercase hex digest.
+    hex: String,
+}
+
+impl BlobAddr {
+    fn parse(path: &Path) -> Option<Self> {
+        let mut comps = path.components().filter_map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            _ => None,
+        });
+        let algorithm = HashAlgorithm::from_name(comps.next()?)?;
+        let hex = comps.next()?.to_ascii_lowercase();
+        if comps.next().is_some() {
+            return None;
+        }
+        if hex.len() != algorithm.hex_len() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(BlobAddr { algorithm, hex })
+    }
+
+    /// the sharded path in the store, e.g. `sha256/ab/cd/abcd...`.
+    fn store_path(&self, root: &Path, shard_levels: usize) -> PathBuf {
+        let mut path = root.join(self.algorithm.name());
+        for level in 0..shard_levels {
+            path.push(&self.hex[level * 2..level * 2 + 2]);
+        }
+        path.push(&self.hex);
+        path
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BlobState {
+    Verified,
+    Corrupted,
+}
+
+/// The opener of the content addressed store, the request path like `/sha256/<hex>`
+/// or `/blake3/<hex>` is mapped to the sharded layout `<root>/<algorithm>/ab/cd/<hex>`.
+/// The hex is used as the strong entity tag and the blob is served as immutable.
+pub struct CasFileReaderOpener {
+    root: PathBuf,
+    shard_levels: usize,
+    verify: bool,
+    states: Arc<Mutex<HashMap<PathBuf, BlobState>>>,
+    executor: SharedExecutor,
+}
+
+impl CasFileReaderOpener {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            shard_levels: DEFAULT_SHARD_LEVELS,
+            verify: false,
+            states: Default::default(),
+            executor: default_executor(),
+        }
+    }
+
+    /// the executor run the blocking file operations, default is the tokio executor.
+    pub fn executor(mut self, executor: impl BlockingExecutor) -> Self {
+        self.executor = Arc::new(executor);
+        self
+    }
+
+    /// the count of the 2-hex-chars directory levels in the layout, default is 2.
+    pub fn shard_levels(mut self, levels: usize) -> Self {
+        // keep the shard inside of the digest.
+        self.shard_levels = levels.min(HashAlgorithm::Sha256.hex_len() / 2);
+        self
+    }
+
+    /// verify the blob hash on the first open, the corrupted blob is unavailable after.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    fn open_blob(
+        addr: BlobAddr,
+        path: PathBuf,
+        verify: bool,
+        states: Arc<Mutex<HashMap<PathBuf, BlobState>>>,
+        executor: SharedExecutor,
+    ) -> Result<FileWithMeta> {
+        let state = states.lock().unwrap().get(&path).copied();
+        if let Some(BlobState::Corrupted) = state {
+            return Err(Error::new(ErrorKind::InvalidData, "corrupted blob"));
+        }
+        let mut file = File::open(&path)?;
+        if verify && state.is_none() && file.metadata()?.is_file() {
+            let hex = addr.algorithm.hex_digest(&mut file)?;
+            let state = if hex == addr.hex {
+                BlobState::Verified
+            } else {
+                BlobState::Corrupted
+            };
+            states.lock().unwrap().insert(path, state);
+            if let BlobState::Corrupted = state {
+                return Err(Error::new(ErrorKind::InvalidData, "corrupted blob"));
+            }
+            file.rewind()?;
+        }
+        let mut file_with_meta = FileWithMeta::from_std(file, &executor)?;
+        file_with_meta.etag = Some(format!("\"{}\"", addr.hex));
+        file_with_meta.immutable = true;
+        Ok(file_with_meta)
+    }
+}
+
+impl FileReaderOpener for CasFileReaderOpener {
+    type Output = FileWithMeta;
+
+    type Future = FileWithMetaFuture;
+
+    fn open<T: AsRef<Path>>(&self, path: T) -> Self::Future {
+        let addr = BlobAddr::parse(path.as_ref());
+        let Self {
+            ref root,
+            shard_levels,
+            verify,
+            ref states,
+            ref executor,
+        } = *self;
+        let store_path = addr
+            .as_ref()
+            .map(|addr| addr.store_path(root, shard_levels));
+        let states = states.clone();
+        let blob_executor = executor.clone();
+        FileWithMetaFuture::spawn(&**executor, move || match (addr, store_path) {
+            (Some(addr), Some(path)) => Self::open_blob(addr, path, verify, states, blob_executor),
+            _ => Err(Error::new(ErrorKind::NotFound, "invalid blob address")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileService,
+    };
+    use hyper::{header, StatusCode};
+
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn test_parse_addr() {
+        let addr = BlobAddr::parse(Path::new(&format!("sha256/{}", EMPTY_SHA256))).unwrap();
+        assert_eq!(addr.algorithm, HashAlgorithm::Sha256);
+        let upper = EMPTY_SHA256.to_ascii_uppercase();
+        let addr2 = BlobAddr::parse(Path::new(&format!("sha256/{}", upper))).unwrap();
+        assert_eq!(addr, addr2);
+        assert!(BlobAddr::parse(Path::new("md5/abc")).is_none());
+        assert!(BlobAddr::parse(Path::new("sha256/abc")).is_none());
+        assert!(BlobAddr::parse(Path::new(&format!("blake3/{}/x", EMPTY_SHA256))).is_none());
+        let path = addr.store_path(Path::new("/store"), 2);
+        assert_eq!(
+            path,
+            PathBuf::from(format!("/store/sha256/e3/b0/{}", EMPTY_SHA256))
+        );
+    }
+
+    #[test]
+    fn test_hex_digest() {
+        let hex = HashAlgorithm::Sha256.hex_digest(&mut &b""[..]).unwrap();
+        assert_eq!(hex, EMPTY_SHA256);
+        let hex = HashAlgorithm::Blake3.hex_digest(&mut &b""[..]).unwrap();
+        assert_eq!(
+            hex,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let root = TempRoot::new("cas");
+        let blob = format!("sha256/e3/b0/{}", EMPTY_SHA256);
+        root.write(&blob, "corrupted");
+        let opener = CasFileReaderOpener::new(root.path()).verify(true);
+        let mut svc = FileService::with_opener(opener);
+        let uri = format!("/sha256/{}", EMPTY_SHA256);
+        runtime().block_on(async {
+            let resp = call(&mut svc, request("GET", &uri, &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+            // the corrupted state is kept, the repaired blob is verified again by the new opener.
+            root.write(&blob, "");
+            let resp = call(&mut svc, request("GET", &uri, &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        });
+        let opener = CasFileReaderOpener::new(root.path()).verify(true);
+        let mut svc = FileService::with_opener(opener);
+        runtime().block_on(async {
+            let resp = call(&mut svc, request("GET", &uri, &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                resp.headers()[header::ETAG],
+                format!("\"{}\"", EMPTY_SHA256)
+            );
+            assert_eq!(
+                resp.headers()[header::CACHE_CONTROL],
+                "public, max-age=31536000, immutable"
+            );
+        });
+    }
+}
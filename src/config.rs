@@ -0,0 +1,820 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    HeaderMap, Response, StatusCode,
+};
+
+#[cfg(feature = "tus")]
+use crate::tus::TusConfig;
+#[cfg(feature = "webdav")]
+use crate::webdav::{LockManager, LockStore};
+use crate::{
+    body::Body,
+    error::Error,
+    error_page::{ErrorContext, ErrorHook, ErrorPage},
+    file::{FileReaderOpener, LocalFileReaderOpener},
+    filesvr::{FileService, FileServiceMaker},
+    glob::glob_match,
+    headers::{self, HostHeaders, PathHeaders},
+    request_resolve::sanitize_path,
+};
+#[cfg(any(feature = "tus", feature = "cors"))]
+use std::time::Duration;
+#[cfg(feature = "cors")]
+use {
+    crate::cors::{CorsConfig, Origin},
+    hyper::Method,
+};
+
+const DEFAULT_MAX_RANGES: usize = 64;
+const DEFAULT_MAX_LISTING_ENTRIES: usize = 10000;
+#[cfg(feature = "archive")]
+const DEFAULT_MAX_ARCHIVE_ENTRIES: usize = 100000;
+
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("toml", "text/plain; charset=utf-8"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+];
+
+/// The precompressed encoding, the encoded file is the sibling with the extension,
+/// e.g. `app.js.br` for `app.js`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// the token of the `Accept-Encoding` and `Content-Encoding`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// the extension of the precompressed file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    /// check the encoding is acceptable by the `Accept-Encoding`, the `q=0` is not acceptable.
+    pub(crate) fn is_accepted(&self, accept_encoding: &str) -> bool {
+        let mut wildcard = false;
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let token = parts.next().unwrap_or("");
+            let accepted = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .all(|q| q.parse::<f32>().map(|q| q > 0.0).unwrap_or(false));
+            if token.eq_ignore_ascii_case(self.name()) {
+                return accepted;
+            }
+            if token == "*" {
+                wildcard = accepted;
+            }
+        }
+        wildcard
+    }
+}
+
+/// the rule of the `Cache-Control`, matched by the glob of the path or the content type.
+#[derive(Debug, Clone)]
+pub(crate) enum CacheRule {
+    Path(String, HeaderValue),
+    Mime(String, HeaderValue),
+}
+
+impl CacheRule {
+    fn matches(&self, path: &str, mime: Option<&str>) -> bool {
+        match (self, mime) {
+            (CacheRule::Path(pattern, _), _) => glob_match(pattern, path),
+            (CacheRule::Mime(pattern, _), Some(mime)) => {
+                let essence = mime.split(';').next().unwrap_or("").trim();
+                match pattern.strip_suffix("/*") {
+                    Some(ty) => essence
+                        .split('/')
+                        .next()
+                        .unwrap_or("")
+                        .eq_ignore_ascii_case(ty),
+                    None => essence.eq_ignore_ascii_case(pattern),
+                }
+            }
+            (CacheRule::Mime(..), None) => false,
+        }
+    }
+
+    fn value(&self) -> &HeaderValue {
+        match self {
+            CacheRule::Path(_, value) | CacheRule::Mime(_, value) => value,
+        }
+    }
+}
+
+/// The configuration shared by all the services of the maker.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    // the index files of the directory, tried in order.
+    pub(crate) index_files: Vec<String>,
+    // render the directory listing when no index file.
+    pub(crate) listing: bool,
+    // the lowercase extension to the content type.
+    pub(crate) mime_types: HashMap<String, String>,
+    pub(crate) default_mime_type: Option<String>,
+    // the `Cache-Control` of the mutable files.
+    pub(crate) cache_control: Option<HeaderValue>,
+    // the rules of the `Cache-Control`, the first matched rule is used.
+    pub(crate) cache_rules: Vec<CacheRule>,
+    // add the `Expires` by the `max-age` of the `Cache-Control`.
+    pub(crate) expires: bool,
+    // serve the files and directories start with the `.`.
+    pub(crate) hidden_files: bool,
+    // the precompressed encodings, tried in order.
+    pub(crate) precompressed: Vec<Encoding>,
+    // the request with more ranges is responded with the whole file.
+    pub(crate) max_ranges: usize,
+    pub(crate) max_listing_entries: usize,
+    // the headers added to the responses, never override the computed headers.
+    pub(crate) headers: HeaderMap,
+    // the headers of the paths, the former rule wins.
+    pub(crate) path_headers: Vec<PathHeaders>,
+    // load the path headers from the `_headers` file in the root.
+    pub(crate) headers_file: bool,
+    // the headers files of the virtual hosts, loaded by the requests.
+    pub(crate) host_headers: Arc<HostHeaders>,
+    // the file served for the missing page of the single page application.
+    pub(crate) spa_fallback: Option<String>,
+    // the paths served by the spa fallback besides the paths without the extension.
+    pub(crate) spa_patterns: Vec<String>,
+    // add the `Content-Disposition` of the file, `attachment` by the `download` query.
+    pub(crate) content_disposition: bool,
+    // the paths always downloaded as the `attachment`.
+    pub(crate) attachment_patterns: Vec<String>,
+    // the pages of the error status.
+    pub(crate) error_pages: HashMap<StatusCode, ErrorPage>,
+    // render the body of the error status without the page.
+    pub(crate) error_bodies: bool,
+    // map the error of the service to the response.
+    pub(crate) error_hook: Option<ErrorHook>,
+    // enable the write methods of the writable opener.
+    pub(crate) write: bool,
+    // create the missing parent directories of the uploaded file.
+    pub(crate) create_dirs: bool,
+    pub(crate) max_upload_size: Option<u64>,
+    // remove the empty directories by the `DELETE`.
+    pub(crate) delete_dirs: bool,
+    // upload the files of the `multipart/form-data` by the `POST`.
+    pub(crate) form_upload: bool,
+    pub(crate) max_form_file_size: Option<u64>,
+    // the endpoint of the tus resumable upload.
+    #[cfg(feature = "tus")]
+    pub(crate) tus: Option<TusConfig>,
+    // serve the WebDAV methods, the methods change the files need the write mode.
+    #[cfg(feature = "webdav")]
+    pub(crate) webdav: bool,
+    // the WebDAV locks shared by all the services.
+    #[cfg(feature = "webdav")]
+    pub(crate) locks: Arc<LockManager>,
+    // stream the archive of the directory by the `archive` query.
+    #[cfg(feature = "archive")]
+    pub(crate) archive: bool,
+    // the zip is stored without the compression, the length is known before the streaming.
+    #[cfg(feature = "archive")]
+    pub(crate) archive_store_only: bool,
+    #[cfg(feature = "archive")]
+    pub(crate) max_archive_entries: usize,
+    // follow the symbolic links out of the root in the archive.
+    #[cfg(feature = "archive")]
+    pub(crate) archive_follow_links: bool,
+    // answer the preflight and add the CORS headers of the allowed origins.
+    #[cfg(feature = "cors")]
+    pub(crate) cors: Option<CorsConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mime_types = MIME_TYPES
+            .iter()
+            .map(|(ext, mime)| (ext.to_string(), mime.to_string()))
+            .collect();
+        Self {
+            index_files: Vec::new(),
+            listing: false,
+            mime_types,
+            default_mime_type: None,
+            cache_control: None,
+            cache_rules: Vec::new(),
+            expires: false,
+            hidden_files: true,
+            precompressed: Vec::new(),
+            max_ranges: DEFAULT_MAX_RANGES,
+            max_listing_entries: DEFAULT_MAX_LISTING_ENTRIES,
+            headers: HeaderMap::new(),
+            path_headers: Vec::new(),
+            headers_file: false,
+            host_headers: Default::default(),
+            spa_fallback: None,
+            spa_patterns: Vec::new(),
+            content_disposition: false,
+            attachment_patterns: Vec::new(),
+            error_pages: HashMap::new(),
+            error_bodies: false,
+            error_hook: None,
+            write: false,
+            create_dirs: false,
+            max_upload_size: None,
+            delete_dirs: false,
+            form_upload: false,
+            max_form_file_size: None,
+            #[cfg(feature = "tus")]
+            tus: None,
+            #[cfg(feature = "webdav")]
+            webdav: false,
+            #[cfg(feature = "webdav")]
+            locks: Arc::default(),
+            #[cfg(feature = "archive")]
+            archive: false,
+            #[cfg(feature = "archive")]
+            archive_store_only: false,
+            #[cfg(feature = "archive")]
+            max_archive_entries: DEFAULT_MAX_ARCHIVE_ENTRIES,
+            #[cfg(feature = "archive")]
+            archive_follow_links: false,
+            #[cfg(feature = "cors")]
+            cors: None,
+        }
+    }
+}
+
+impl Config {
+    /// the content type of the path by the extension.
+    pub(crate) fn mime_type(&self, path: &Path) -> Option<&str> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.mime_types.get(&ext.to_ascii_lowercase()))
+            .or(self.default_mime_type.as_ref())
+            .map(String::as_str)
+    }
+
+    /// the `Cache-Control` of the mutable file by the first matched rule, or the default.
+    pub(crate) fn cache_policy(&self, path: &str, mime: Option<&str>) -> Option<HeaderValue> {
+        self.cache_rules
+            .iter()
+            .find(|rule| rule.matches(path, mime))
+            .map(|rule| rule.value().clone())
+            .or_else(|| self.cache_control.clone())
+    }
+
+    /// the path has the component start with the `.`, or it's under the tus uploads.
+    pub(crate) fn is_hidden(&self, path: &Path) -> bool {
+        #[cfg(feature = "tus")]
+        if let Some(ref tus) = self.tus {
+            if tus.is_upload_path(path) {
+                return true;
+            }
+        }
+        !self.hidden_files
+            && path
+                .iter()
+                .any(|c| c.to_str().map(|c| c.starts_with('.')).unwrap_or(true))
+    }
+
+    /// the missing page is served by the spa fallback, the asset with the extension is not.
+    pub(crate) fn is_spa_path(&self, path: &str) -> bool {
+        if self.spa_fallback.is_none() {
+            return false;
+        }
+        let name = path.rsplit('/').next().unwrap_or("");
+        !name.contains('.') || self.spa_patterns.iter().any(|p| glob_match(p, path))
+    }
+
+    /// the methods of the `Allow` header, the write methods are listed when they are enabled.
+    pub(crate) fn allow_header(&self, writable: bool) -> HeaderValue {
+        let writable = self.write && writable;
+        let mut methods = String::from("GET, HEAD, OPTIONS");
+        if writable {
+            methods.push_str(", PUT, PATCH, DELETE");
+        }
+        if writable && self.form_upload {
+            methods.push_str(", POST");
+        }
+        #[cfg(feature = "webdav")]
+        if self.webdav {
+            methods.push_str(", ");
+            methods.push_str(crate::webdav::READ_METHODS);
+            if writable {
+                methods.push_str(", ");
+                methods.push_str(crate::webdav::WRITE_METHODS);
+            }
+        }
+        HeaderValue::from_str(&methods).unwrap()
+    }
+
+    /// add the custom headers which not in the response.
+    pub(crate) fn apply_headers(&self, headers: &mut HeaderMap) {
+        for name in self.headers.keys() {
+            if headers.contains_key(name) {
+                continue;
+            }
+            for value in self.headers.get_all(name) {
+                headers.append(name, value.clone());
+            }
+        }
+    }
+}
+
+/// The builder of the file service and the maker.
+///
+/// ```ignore
+/// let maker = FileServiceBuilder::new("./public")
+///     .index_file("index.html")
+///     .listing(true)
+///     .precompressed(Encoding::Brotli)
+///     .build_maker();
+/// ```
+pub struct FileServiceBuilder<O = LocalFileReaderOpener> {
+    opener: O,
+    config: Config,
+}
+
+impl FileServiceBuilder {
+    pub fn new(root: impl Into<String>) -> Self {
+        Self::with_opener(LocalFileReaderOpener::new(root.into()))
+    }
+}
+
+impl<O: FileReaderOpener> FileServiceBuilder<O> {
+    /// create the builder with the opener of the backend.
+    pub fn with_opener(opener: O) -> Self {
+        Self {
+            opener,
+            config: Config::default(),
+        }
+    }
+
+    /// add the index file served for the directory, e.g. `index.html`.
+    pub fn index_file(mut self, name: impl Into<String>) -> Self {
+        self.config.index_files.push(name.into());
+        self
+    }
+
+    /// render the html listing for the directory without the index file, default is false.
+    pub fn listing(mut self, listing: bool) -> Self {
+        self.config.listing = listing;
+        self
+    }
+
+    /// add or override the content type of the extension.
+    pub fn mime_type(mut self, ext: impl Into<String>, mime: impl Into<String>) -> Self {
+        let ext = ext.into().trim_start_matches('.').to_ascii_lowercase();
+        self.config.mime_types.insert(ext, mime.into());
+        self
+    }
+
+    /// the content type of the file with the unknown extension, default is none.
+    pub fn default_mime_type(mut self, mime: impl Into<String>) -> Self {
+        self.config.default_mime_type = Some(mime.into());
+        self
+    }
+
+    /// the `Cache-Control` of the files, the immutable files always use the immutable policy.
+    pub fn cache_control(mut self, value: HeaderValue) -> Self {
+        self.config.cache_control = Some(value);
+        self
+    }
+
+    /// the `Cache-Control` of the paths match the glob pattern, e.g. `/assets/**` of the hashed
+    /// assets. the rules are tried in the added order before the default.
+    pub fn cache_control_path(mut self, pattern: impl Into<String>, value: HeaderValue) -> Self {
+        let rule = CacheRule::Path(pattern.into(), value);
+        self.config.cache_rules.push(rule);
+        self
+    }
+
+    /// the `Cache-Control` of the content type, e.g. `text/html` or `image/*`.
+    pub fn cache_control_mime(mut self, mime: impl Into<String>, value: HeaderValue) -> Self {
+        let rule = CacheRule::Mime(mime.into(), value);
+        self.config.cache_rules.push(rule);
+        self
+    }
+
+    /// add the `Expires` computed by the `max-age` of the `Cache-Control` for the old caches,
+    /// default is false.
+    pub fn expires(mut self, expires: bool) -> Self {
+        self.config.expires = expires;
+        self
+    }
+
+    /// serve the hidden files start with the `.`, default is true.
+    pub fn hidden_files(mut self, hidden_files: bool) -> Self {
+        self.config.hidden_files = hidden_files;
+        self
+    }
+
+    /// serve the precompressed sibling file if the client accepts the encoding,
+    /// the encodings are tried in the added order.
+    pub fn precompressed(mut self, encoding: Encoding) -> Self {
+        if !self.config.precompressed.contains(&encoding) {
+            self.config.precompressed.push(encoding);
+        }
+        self
+    }
+
+    /// the request with more ranges is responded with the whole file, default is 64.
+    pub fn max_ranges(mut self, max_ranges: usize) -> Self {
+        self.config.max_ranges = max_ranges;
+        self
+    }
+
+    /// the max entries in the directory listing, default is 10000.
+    pub fn max_listing_entries(mut self, max_entries: usize) -> Self {
+        self.config.max_listing_entries = max_entries;
+        self
+    }
+
+    /// add the header to the responses, the header computed by the service is not overridden.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.config.headers.append(name, value);
+        self
+    }
+
+    /// add the header to the files of the paths match the glob pattern, e.g. the
+    /// `Content-Security-Policy` of `*.html`. the computed headers are not overridden.
+    pub fn path_header(
+        mut self,
+        pattern: impl Into<String>,
+        name: HeaderName,
+        value: HeaderValue,
+    ) -> Self {
+        let pattern = pattern.into();
+        let rules = &mut self.config.path_headers;
+        match rules.iter_mut().find(|r| r.pattern == pattern) {
+            Some(rule) => {
+                rule.headers.append(name, value);
+            }
+            None => {
+                let mut headers = HeaderMap::new();
+                headers.append(name, value);
+                rules.push(PathHeaders { pattern, headers });
+            }
+        }
+        self
+    }
+
+    /// load the path headers from the `_headers` file in the root when the service is built,
+    /// the headers added by the `path_header` take precedence, default is false.
+    pub fn headers_file(mut self, headers_file: bool) -> Self {
+        self.config.headers_file = headers_file;
+        self
+    }
+
+    /// serve the file, e.g. `index.html`, with `200` for the missing page of the single page
+    /// application. The page is the `GET` accepts the html, and the path without the extension.
+    pub fn spa_fallback(mut self, path: impl Into<String>) -> Self {
+        self.config.spa_fallback = Some(path.into());
+        self
+    }
+
+    /// the glob pattern of the paths served by the spa fallback besides the paths without
+    /// the extension, e.g. `/app/**`.
+    pub fn spa_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.config.spa_patterns.push(pattern.into());
+        self
+    }
+
+    /// add the `Content-Disposition` with the filename to the files, the file is `inline` and
+    /// the `?download` or `?download=name` makes it the `attachment`, default is false.
+    pub fn content_disposition(mut self, content_disposition: bool) -> Self {
+        self.config.content_disposition = content_disposition;
+        self
+    }
+
+    /// the glob pattern of the paths always served as the `attachment`, e.g. `*.zip`, the
+    /// `Content-Disposition` is enabled.
+    pub fn attachment_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.config.content_disposition = true;
+        self.config.attachment_patterns.push(pattern.into());
+        self
+    }
+
+    /// serve the file under the root as the html page of the error status, the client which
+    /// not accepts the html gets the json problem details or the plain text.
+    pub fn error_page(mut self, status: StatusCode, path: impl AsRef<str>) -> Self {
+        let page = ErrorPage::File(sanitize_path(path.as_ref()));
+        self.config.error_pages.insert(status, page);
+        self
+    }
+
+    /// the handler builds the whole response of the error status.
+    pub fn error_handler<F>(mut self, status: StatusCode, handler: F) -> Self
+    where
+        F: Fn(&ErrorContext<'_>) -> Response<Body> + Send + Sync + 'static,
+    {
+        let page = ErrorPage::Handler(Arc::new(handler));
+        self.config.error_pages.insert(status, page);
+        self
+    }
+
+    /// render the html, the json problem details (RFC 9457) or the plain text by the `Accept`
+    /// for the error status without the page, default is false, the body is empty.
+    pub fn error_bodies(mut self, error_bodies: bool) -> Self {
+        self.config.error_bodies = error_bodies;
+        self
+    }
+
+    /// map the error of the service to the response, e.g. `500 Internal Server Error`,
+    /// without the hook the error is returned and hyper closes the connection.
+    pub fn on_error<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Error) -> Response<Body> + Send + Sync + 'static,
+    {
+        self.config.error_hook = Some(ErrorHook(Arc::new(hook)));
+        self
+    }
+
+    /// enable the `PUT` upload and the `DELETE` of the writable opener, e.g. the local files,
+    /// default is false.
+    /// the body is written to the temporary file and renamed to the target.
+    pub fn write(mut self, write: bool) -> Self {
+        self.config.write = write;
+        self
+    }
+
+    /// create the missing parent directories of the uploaded file, default is false,
+    /// the upload is responded with `409 Conflict`.
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.config.create_dirs = create_dirs;
+        self
+    }
+
+    /// the upload with the larger body is responded with `413 Payload Too Large`, the range
+    /// upload is limited to 64GiB without it.
+    pub fn max_upload_size(mut self, max_size: u64) -> Self {
+        self.config.max_upload_size = Some(max_size);
+        self
+    }
+
+    /// remove the empty directories by the `DELETE`, default is false, the directory is
+    /// responded with `409 Conflict`.
+    pub fn delete_dirs(mut self, delete_dirs: bool) -> Self {
+        self.config.delete_dirs = delete_dirs;
+        self
+    }
+
+    /// upload the files of the `multipart/form-data` form into the directory by the `POST`,
+    /// the write mode should be enabled, the `max_upload_size` limits the whole form.
+    pub fn form_upload(mut self, form_upload: bool) -> Self {
+        self.config.form_upload = form_upload;
+        self
+    }
+
+    /// the form with the larger file is responded with `413 Payload Too Large`.
+    pub fn max_form_file_size(mut self, max_size: u64) -> Self {
+        self.config.max_form_file_size = Some(max_size);
+        self
+    }
+
+    /// enable the tus 1.0 resumable upload under the endpoint, e.g. `/files`, the write mode
+    /// should be enabled. the uploads are the files under the endpoint.
+    #[cfg(feature = "tus")]
+    pub fn tus(mut self, endpoint: impl AsRef<str>) -> Self {
+        let expiration = self.config.tus.as_ref().and_then(|t| t.expiration);
+        let mut tus = TusConfig::new(endpoint.as_ref());
+        tus.expiration = expiration;
+        self.config.tus = Some(tus);
+        self
+    }
+
+    /// the incomplete tus upload is removed after the expiration, default is never.
+    #[cfg(feature = "tus")]
+    pub fn tus_expiration(mut self, expiration: Duration) -> Self {
+        let tus = self
+            .config
+            .tus
+            .get_or_insert_with(|| TusConfig::new("/files"));
+        tus.expiration = Some(expiration);
+        self
+    }
+
+    /// serve the WebDAV class 1 methods, the clients mount the files as the network drive.
+    /// the `PROPFIND` lists the files, the other methods need the write mode.
+    #[cfg(feature = "webdav")]
+    pub fn webdav(mut self, webdav: bool) -> Self {
+        self.config.webdav = webdav;
+        self
+    }
+
+    /// save the WebDAV locks to the store, the locks are loaded from the store when the
+    /// builder is called, default the locks are only kept in the memory.
+    #[cfg(feature = "webdav")]
+    pub fn webdav_lock_store(mut self, store: impl LockStore + 'static) -> Self {
+        self.config.locks = Arc::new(LockManager::with_store(Arc::new(store)));
+        self
+    }
+
+    /// download the directory as the streamed archive, e.g. `/dir/?archive=zip`, the formats
+    /// are `zip`, `tar` and `tar.gz`. the hidden files are not archived.
+    #[cfg(feature = "archive")]
+    pub fn archive(mut self, archive: bool) -> Self {
+        self.config.archive = archive;
+        self
+    }
+
+    /// store the files of the zip without the compression, the `Content-Length` of the zip is
+    /// computed before the streaming. the tar always has the `Content-Length`.
+    #[cfg(feature = "archive")]
+    pub fn archive_store_only(mut self, store_only: bool) -> Self {
+        self.config.archive_store_only = store_only;
+        self
+    }
+
+    /// follow the symbolic links which point out of the root in the archive, default is false,
+    /// the links are skipped. the links in the root are always followed, the policy needs the
+    /// opener with the local files.
+    #[cfg(feature = "archive")]
+    pub fn archive_follow_links(mut self, follow_links: bool) -> Self {
+        self.config.archive_follow_links = follow_links;
+        self
+    }
+
+    /// the directory with more entries is not archived, default is 100000.
+    #[cfg(feature = "archive")]
+    pub fn max_archive_entries(mut self, max_entries: usize) -> Self {
+        self.config.max_archive_entries = max_entries;
+        self
+    }
+
+    /// allow the origin of the CORS, the exact origin, e.g. `https://app.example.com`, the
+    /// wildcard, e.g. `https://*.example.com`, or `*` of any origin.
+    #[cfg(feature = "cors")]
+    pub fn cors_origin(mut self, origin: impl AsRef<str>) -> Self {
+        let origin = Origin::new(origin.as_ref());
+        self.cors().origins.push(origin);
+        self
+    }
+
+    /// allow the origins match the regex of the CORS, e.g. `^https://app\d+\.example\.com$`.
+    ///
+    /// # Panics
+    ///
+    /// panics if the regex is invalid.
+    #[cfg(feature = "cors")]
+    pub fn cors_origin_regex(mut self, regex: impl AsRef<str>) -> Self {
+        let regex = regex::Regex::new(regex.as_ref()).expect("the invalid regex of the origin");
+        self.cors().origins.push(Origin::Regex(regex));
+        self
+    }
+
+    /// add the method allowed by the preflight, default is the methods of the `Allow`.
+    #[cfg(feature = "cors")]
+    pub fn cors_method(mut self, method: Method) -> Self {
+        self.cors().methods.push(method);
+        self
+    }
+
+    /// add the request header allowed by the preflight, the `Range`, the conditional headers
+    /// and the `Content-Type` are always allowed.
+    #[cfg(feature = "cors")]
+    pub fn cors_allow_header(mut self, name: HeaderName) -> Self {
+        self.cors().allow_headers.push(name);
+        self
+    }
+
+    /// add the response header exposed to the script, the `Content-Range`, `Accept-Ranges`,
+    /// `ETag`, `Content-Length` and `Last-Modified` are always exposed.
+    #[cfg(feature = "cors")]
+    pub fn cors_expose_header(mut self, name: HeaderName) -> Self {
+        self.cors().expose_headers.push(name);
+        self
+    }
+
+    /// allow the credentials of the CORS request, the allowed origin is echoed, default is false.
+    ///
+    /// # Panics
+    ///
+    /// the build panics if the credentials are allowed with the `*` origin.
+    #[cfg(feature = "cors")]
+    pub fn cors_credentials(mut self, credentials: bool) -> Self {
+        self.cors().credentials = credentials;
+        self
+    }
+
+    /// the preflight result is cached by the client for the max age.
+    #[cfg(feature = "cors")]
+    pub fn cors_max_age(mut self, max_age: Duration) -> Self {
+        self.cors().max_age = Some(max_age);
+        self
+    }
+
+    #[cfg(feature = "cors")]
+    fn cors(&mut self) -> &mut CorsConfig {
+        self.config.cors.get_or_insert_with(CorsConfig::default)
+    }
+
+    pub fn build(mut self) -> FileService<O> {
+        self.finish();
+        FileService::with_config(Arc::new(self.opener), Arc::new(self.config))
+    }
+
+    pub fn build_maker(mut self) -> FileServiceMaker<O> {
+        self.finish();
+        FileServiceMaker::with_config(Arc::new(self.opener), Arc::new(self.config))
+    }
+
+    /// the steps of the config before it's shared by the services.
+    fn finish(&mut self) {
+        self.load_headers_file();
+        #[cfg(all(feature = "tus", feature = "cors"))]
+        self.tus_cors();
+        #[cfg(feature = "cors")]
+        if let Some(ref cors) = self.config.cors {
+            cors.validate();
+        }
+    }
+
+    /// the tus clients in the browser send and read the headers of the protocol cross origin.
+    #[cfg(all(feature = "tus", feature = "cors"))]
+    fn tus_cors(&mut self) {
+        if let (Some(_), Some(cors)) = (&self.config.tus, &mut self.config.cors) {
+            let allow = crate::tus::ALLOW_HEADERS.iter();
+            cors.allow_headers
+                .extend(allow.map(|n| HeaderName::from_static(n)));
+            let expose = crate::tus::EXPOSE_HEADERS.iter();
+            cors.expose_headers
+                .extend(expose.map(|n| HeaderName::from_static(n)));
+        }
+    }
+
+    /// add the rules of the headers file after the rules of the builder.
+    fn load_headers_file(&mut self) {
+        if self.config.headers_file && !self.opener.is_virtual_host() {
+            let rules = headers::load(&self.opener);
+            self.config.path_headers.extend(rules);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accept_encoding() {
+        assert!(Encoding::Brotli.is_accepted("gzip, deflate, br"));
+        assert!(!Encoding::Brotli.is_accepted("gzip, br;q=0"));
+        assert!(Encoding::Gzip.is_accepted("*"));
+        assert!(!Encoding::Gzip.is_accepted("*, gzip;q=0"));
+        assert!(!Encoding::Gzip.is_accepted("identity"));
+    }
+
+    #[test]
+    fn test_mime_type() {
+        let config = Config::default();
+        let mime = |p: &str| config.mime_type(Path::new(p));
+        assert_eq!(mime("a/index.HTML").unwrap(), "text/html; charset=utf-8");
+        assert!(mime("a/noext").is_none());
+        assert!(!config.is_hidden(Path::new(".git/config")));
+        let config = Config {
+            hidden_files: false,
+            ..Config::default()
+        };
+        assert!(config.is_hidden(Path::new("a/.git/config")));
+        assert!(!config.is_hidden(Path::new("a/b.txt")));
+    }
+}
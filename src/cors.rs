@@ -0,0 +1,284 @@
+//! The CORS of the files, the preflight `OPTIONS` is answered by the service and the allowed
+//! origin is added to the responses, the range clients read the exposed headers.
+use std::time::Duration;
+
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    HeaderMap, Method, Request, Response, StatusCode,
+};
+use regex::Regex;
+
+use crate::{body::Body, glob::glob_match};
+
+// the headers of the conditional and the range requests.
+const DEFAULT_ALLOW_HEADERS: &[&str] = &[
+    "range",
+    "if-range",
+    "if-match",
+    "if-none-match",
+    "if-modified-since",
+    "content-type",
+];
+// the range clients need the headers to resume and validate the ranges.
+const DEFAULT_EXPOSE_HEADERS: &[&str] = &[
+    "content-range",
+    "accept-ranges",
+    "etag",
+    "content-length",
+    "last-modified",
+];
+
+/// the origin allowed by the CORS.
+#[derive(Debug, Clone)]
+pub(crate) enum Origin {
+    Any,
+    Exact(String),
+    // the `*` matches the characters except the `/`, e.g. `https://*.example.com`.
+    Wildcard(String),
+    Regex(Regex),
+}
+
+impl Origin {
+    pub(crate) fn new(origin: &str) -> Self {
+        match origin {
+            "*" => Origin::Any,
+            o if o.contains('*') => Origin::Wildcard(o.to_string()),
+            o => Origin::Exact(o.to_string()),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Origin::Any => true,
+            Origin::Exact(o) => o.eq_ignore_ascii_case(origin),
+            Origin::Wildcard(pattern) => glob_match(pattern, origin),
+            Origin::Regex(regex) => regex.is_match(origin),
+        }
+    }
+}
+
+/// the `OPTIONS` with the `Access-Control-Request-Method` is the preflight.
+pub(crate) fn is_preflight<B>(r: &Request<B>) -> bool {
+    r.method() == Method::OPTIONS
+        && r.headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// The configuration of the CORS.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CorsConfig {
+    pub(crate) origins: Vec<Origin>,
+    // the methods of the preflight, default is the methods of the `Allow`.
+    pub(crate) methods: Vec<Method>,
+    // the request headers besides the default headers.
+    pub(crate) allow_headers: Vec<HeaderName>,
+    // the response headers besides the default headers.
+    pub(crate) expose_headers: Vec<HeaderName>,
+    pub(crate) credentials: bool,
+    pub(crate) max_age: Option<Duration>,
+}
+
+impl CorsConfig {
+    /// the `Access-Control-Allow-Origin` of the request origin, none if it's not allowed.
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        let origin_str = origin.to_str().ok()?;
+        let allowed = self.origins.iter().find(|o| o.matches(origin_str))?;
+        match allowed {
+            Origin::Any => Some(HeaderValue::from_static("*")),
+            _ => Some(origin.clone()),
+        }
+    }
+
+    /// the credentials with the `*` origin would allow any site to read the credentialed
+    /// responses, it's rejected when the service is built.
+    pub(crate) fn validate(&self) {
+        let is_any = self.origins.iter().any(|o| matches!(o, Origin::Any));
+        assert!(
+            !(self.credentials && is_any),
+            "the CORS credentials can't be allowed with the `*` origin, list the origins instead"
+        );
+    }
+
+    /// the allowed origin is not the `*`, the caches should vary by the `Origin`.
+    fn varies(&self) -> bool {
+        self.credentials || self.origins.iter().any(|o| !matches!(o, Origin::Any))
+    }
+
+    fn is_header_allowed(&self, name: &str) -> bool {
+        DEFAULT_ALLOW_HEADERS
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(name))
+            || self
+                .allow_headers
+                .iter()
+                .any(|h| h.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// answer the preflight request, none if the request is not the preflight or it's not
+    /// allowed, the request is served as the normal `OPTIONS` without the CORS headers.
+    pub(crate) fn preflight<B>(
+        &self,
+        r: &Request<B>,
+        allow: &HeaderValue,
+    ) -> Option<Response<Body>> {
+        if !is_preflight(r) {
+            return None;
+        }
+        let headers = r.headers();
+        let origin = headers.get(header::ORIGIN)?;
+        let method = headers.get(header::ACCESS_CONTROL_REQUEST_METHOD)?;
+        let allow_origin = self.allow_origin(origin)?;
+        let method = method.to_str().ok()?;
+        let methods = if self.methods.is_empty() {
+            allow.to_str().ok()?.to_string()
+        } else {
+            let methods: Vec<_> = self.methods.iter().map(Method::as_str).collect();
+            methods.join(", ")
+        };
+        if !methods.split(',').any(|m| m.trim() == method) {
+            return None;
+        }
+        let request_headers = headers
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .map(|h| h.to_str().unwrap_or(","))
+            .unwrap_or("");
+        let request_headers: Vec<&str> = request_headers
+            .split(',')
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .collect();
+        if !request_headers.iter().all(|h| self.is_header_allowed(h)) {
+            return None;
+        }
+        let mut builder = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin)
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, methods)
+            .header(
+                header::VARY,
+                "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
+            );
+        if !request_headers.is_empty() {
+            let allow_headers = request_headers.join(", ").to_ascii_lowercase();
+            builder = builder.header(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if self.credentials {
+            builder = builder.header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+        }
+        if let Some(max_age) = self.max_age {
+            builder = builder.header(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs());
+        }
+        builder.body(Body::Empty).ok()
+    }
+
+    /// add the CORS headers of the allowed origin to the response.
+    pub(crate) fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if self.varies() {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        let allow_origin = match origin.and_then(|o| self.allow_origin(o)) {
+            Some(allow_origin) => allow_origin,
+            None => return,
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.credentials {
+            let value = HeaderValue::from_static("true");
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, value);
+        }
+        let mut expose = DEFAULT_EXPOSE_HEADERS.join(", ");
+        for name in self.expose_headers.iter() {
+            expose.push_str(", ");
+            expose.push_str(name.as_str());
+        }
+        if let Ok(expose) = HeaderValue::from_str(&expose) {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_origin() {
+        assert!(Origin::new("*").matches("https://a.com"));
+        assert!(Origin::new("https://a.com").matches("https://A.com"));
+        assert!(!Origin::new("https://a.com").matches("https://a.com.evil"));
+        let wildcard = Origin::new("https://*.example.com");
+        assert!(wildcard.matches("https://cdn.example.com"));
+        assert!(!wildcard.matches("https://example.com"));
+        assert!(!wildcard.matches("https://evil.com/.example.com"));
+        let regex = Origin::Regex(Regex::new(r"^https://app\d+\.local$").unwrap());
+        assert!(regex.matches("https://app1.local"));
+        assert!(!regex.matches("https://app.local"));
+    }
+
+    #[test]
+    fn test_cors() {
+        let root = TempRoot::new("cors");
+        root.write("app.wasm", "wasm");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .cors_origin("https://*.example.com")
+            .cors_origin_regex(r"^https://app\d+\.local$")
+            .cors_credentials(true)
+            .cors_max_age(Duration::from_secs(600))
+            .build();
+        runtime().block_on(async {
+            let preflight = [
+                ("origin", "https://cdn.example.com"),
+                ("access-control-request-method", "GET"),
+                ("access-control-request-headers", "Range"),
+            ];
+            let resp = call(&mut svc, request("OPTIONS", "/app.wasm", &preflight, "")).await;
+            let headers = resp.headers();
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://cdn.example.com"
+            );
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "range");
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+            assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+            // the method is not allowed, the normal `OPTIONS` without the CORS.
+            let preflight = [
+                ("origin", "https://cdn.example.com"),
+                ("access-control-request-method", "PUT"),
+            ];
+            let resp = call(&mut svc, request("OPTIONS", "/app.wasm", &preflight, "")).await;
+            assert!(!resp
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+            let get = [("origin", "https://app1.local"), ("range", "bytes=0-1")];
+            let resp = call(&mut svc, request("GET", "/app.wasm", &get, "")).await;
+            let headers = resp.headers();
+            assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://app1.local"
+            );
+            let expose = headers[header::ACCESS_CONTROL_EXPOSE_HEADERS].to_str();
+            assert!(expose.unwrap().contains("content-range"));
+            assert_eq!(headers[header::VARY], "Origin");
+            let get = [("origin", "https://evil.com")];
+            let resp = call(&mut svc, request("GET", "/app.wasm", &get, "")).await;
+            assert!(!resp
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "the CORS credentials can't be allowed with the `*` origin")]
+    fn test_any_origin_credentials() {
+        FileServiceBuilder::new(".")
+            .cors_origin("*")
+            .cors_credentials(true)
+            .build();
+    }
+}
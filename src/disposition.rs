@@ -0,0 +1,141 @@
+//! The `Content-Disposition` of the served file, the filename is encoded by the RFC 6266 and
+//! the RFC 5987, the client saves the name out of the ascii correctly.
+use std::path::Path;
+
+use hyper::{header::HeaderValue, Request};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::{
+    config::Config, form::sanitize_filename, glob::glob_match, request_resolve::decode_percents,
+};
+
+// the `attr-char` of the RFC 5987 is not encoded.
+const ATTR_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// the `Content-Disposition` of the resolved file, none if it's not enabled. the filename is
+/// the name of the resolved path or the value of the `download` query.
+pub(crate) fn disposition<B>(config: &Config, path: &Path, r: &Request<B>) -> Option<HeaderValue> {
+    if !config.content_disposition {
+        return None;
+    }
+    let download = r.uri().query().and_then(download_param);
+    let uri_path = decode_percents(r.uri().path());
+    let attachment = download.is_some()
+        || config
+            .attachment_patterns
+            .iter()
+            .any(|p| glob_match(p, &uri_path));
+    let filename = match download.flatten() {
+        Some(filename) => filename,
+        None => path.file_name()?.to_string_lossy().into_owned(),
+    };
+    Some(content_disposition(attachment, &filename))
+}
+
+/// the `download` parameter of the query, the value is the filename of the download, e.g.
+/// `?download=report.pdf`. none if the query has no `download`.
+pub(crate) fn download_param(query: &str) -> Option<Option<String>> {
+    query.split('&').find_map(|param| {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        if name != "download" {
+            return None;
+        }
+        let value = percent_decode_str(&value.replace('+', " "))
+            .decode_utf8_lossy()
+            .into_owned();
+        Some(sanitize_filename(&value))
+    })
+}
+
+/// the `Content-Disposition` with the filename, the `filename*` is added for the name out of
+/// the ascii, the `filename` is the ascii fallback for the old client.
+pub(crate) fn content_disposition(attachment: bool, filename: &str) -> HeaderValue {
+    let kind = if attachment { "attachment" } else { "inline" };
+    let fallback = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' | '%' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let value = if fallback == filename {
+        format!("{kind}; filename=\"{fallback}\"")
+    } else {
+        let encoded = utf8_percent_encode(filename, ATTR_ENCODE_SET);
+        format!("{kind}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+    };
+    HeaderValue::from_str(&value).expect("the disposition is ascii")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+    use hyper::header;
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition(true, "a b.txt"),
+            "attachment; filename=\"a b.txt\""
+        );
+        assert_eq!(
+            content_disposition(false, "résumé \"1\".pdf"),
+            "inline; filename=\"r_sum_ _1_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%221%22.pdf"
+        );
+        assert_eq!(download_param("a=1&download"), Some(None));
+        assert_eq!(
+            download_param("download=..%2Fr%C3%A9sum%C3%A9+1.pdf"),
+            Some(Some("résumé 1.pdf".to_string()))
+        );
+        assert_eq!(download_param("downloads=1"), None);
+    }
+
+    #[test]
+    fn test_disposition() {
+        let root = TempRoot::new("disposition");
+        root.write("résumé.pdf", "pdf").write("a.zip", "zip");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .attachment_pattern("*.zip")
+            .build();
+        runtime().block_on(async {
+            let mut get = |path: &str| call(&mut svc, request("GET", path, &[], ""));
+            let resp = get("/r%C3%A9sum%C3%A9.pdf").await;
+            assert_eq!(
+                resp.headers()[header::CONTENT_DISPOSITION],
+                "inline; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+            );
+            let resp = get("/r%C3%A9sum%C3%A9.pdf?download").await;
+            assert_eq!(
+                resp.headers()[header::CONTENT_DISPOSITION],
+                "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+            );
+            let resp = get("/r%C3%A9sum%C3%A9.pdf?download=cv.pdf").await;
+            assert_eq!(
+                resp.headers()[header::CONTENT_DISPOSITION],
+                "attachment; filename=\"cv.pdf\""
+            );
+            let resp = get("/a.zip").await;
+            assert_eq!(
+                resp.headers()[header::CONTENT_DISPOSITION],
+                "attachment; filename=\"a.zip\""
+            );
+        });
+    }
+}
@@ -1,4 +1,8 @@
-use std::fmt::{self, Display, Formatter};
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+    io,
+};
 
 #[derive(Debug)]
 pub enum ParseError {
@@ -16,3 +20,176 @@ impl Display for ParseError {
         }
     }
 }
+
+impl StdError for ParseError {}
+
+/// The boxed error of the request body or the inner service.
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// the marker of the io error, the blocking task is dropped by the executor.
+#[derive(Debug)]
+pub(crate) struct JoinError;
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "error execute in background.")
+    }
+}
+
+impl StdError for JoinError {}
+
+/// The error of the file service.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// the request can't be resolved to the path, e.g. the invalid request message.
+    Resolve(io::Error),
+    /// the `Range` header is invalid.
+    RangeParse(ParseError),
+    /// the io error of the backend.
+    Io(io::Error),
+    /// the response can't be built with the headers.
+    Header(hyper::http::Error),
+    /// the blocking task is dropped before it's completed.
+    Join,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Resolve(ref e) => write!(f, "resolve request error: {e}"),
+            Error::RangeParse(ref e) => write!(f, "parse range error: {e}"),
+            Error::Io(ref e) => write!(f, "backend io error: {e}"),
+            Error::Header(ref e) => write!(f, "build header error: {e}"),
+            Error::Join => write!(f, "{JoinError}"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::Resolve(ref e) | Error::Io(ref e) => Some(e),
+            Error::RangeParse(ref e) => Some(e),
+            Error::Header(ref e) => Some(e),
+            Error::Join => None,
+        }
+    }
+}
+
+impl Error {
+    /// the error of the resolver, the blocking task dropped is still the `Join`.
+    pub(crate) fn resolve(e: io::Error) -> Self {
+        match Error::from(e) {
+            Error::Io(e) => Error::Resolve(e),
+            e => e,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().map(|e| e.is::<JoinError>()).unwrap_or(false) {
+            Error::Join
+        } else {
+            Error::Io(e)
+        }
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(e: hyper::http::Error) -> Self {
+        Error::Header(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::RangeParse(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) | Error::Resolve(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_join_error() {
+        let e: Error = io::Error::other(JoinError).into();
+        assert!(matches!(e, Error::Join));
+        let e: Error = io::Error::from(io::ErrorKind::TimedOut).into();
+        assert!(matches!(e, Error::Io(_)));
+        assert!(e.source().is_some());
+        let e = Error::resolve(io::Error::other(JoinError));
+        assert!(matches!(e, Error::Join));
+        let e = Error::resolve(io::Error::from(io::ErrorKind::InvalidInput));
+        assert!(matches!(e, Error::Resolve(_)));
+        let e: Error = ParseError::InvalidRange.into();
+        assert!(matches!(e, Error::RangeParse(ParseError::InvalidRange)));
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_error_hook() {
+        use crate::{BlockingExecutor, BlockingTask, FileServiceBuilder, LocalFileReaderOpener};
+        use hyper::{service::Service, Request, Response, StatusCode};
+
+        // the executor drops the tasks.
+        struct DropExecutor;
+
+        impl BlockingExecutor for DropExecutor {
+            fn execute(&self, _task: BlockingTask) {}
+        }
+
+        let opener = LocalFileReaderOpener::new(".").executor(DropExecutor);
+        let mut svc = FileServiceBuilder::with_opener(opener)
+            .on_error(|e| {
+                assert!(matches!(e, Error::Join));
+                let mut resp = Response::new(crate::Body::Empty);
+                *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                resp
+            })
+            .build();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let req = Request::get("/Cargo.toml")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let resp = rt.block_on(svc.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_resolve_error() {
+        use crate::FileServiceBuilder;
+        use hyper::{service::Service, Request, Response, StatusCode};
+
+        // the file is not the directory, the resolver fails with the io error.
+        let mut svc = FileServiceBuilder::new(".")
+            .on_error(|e| {
+                assert!(matches!(e, Error::Resolve(_)));
+                let mut resp = Response::new(crate::Body::Empty);
+                *resp.status_mut() = StatusCode::BAD_GATEWAY;
+                resp
+            })
+            .build();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let req = Request::get("/Cargo.toml/x")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let resp = rt.block_on(svc.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use hyper::{
+    header::{self, HeaderValue},
+    HeaderMap, Method, Response, StatusCode, Uri,
+};
+
+use crate::{body::Body, error::Error, listing::escape_html};
+
+pub(crate) const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The request info passed to the error handler.
+pub struct ErrorContext<'a> {
+    pub status: StatusCode,
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub headers: &'a HeaderMap,
+}
+
+pub(crate) type ErrorHandler = Arc<dyn Fn(&ErrorContext<'_>) -> Response<Body> + Send + Sync>;
+
+/// the hook maps the error of the service to the response.
+#[derive(Clone)]
+pub(crate) struct ErrorHook(pub(crate) ErrorHookFn);
+
+pub(crate) type ErrorHookFn = Arc<dyn Fn(&Error) -> Response<Body> + Send + Sync>;
+
+impl fmt::Debug for ErrorHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorHook")
+    }
+}
+
+/// the page of the error status.
+#[derive(Clone)]
+pub(crate) enum ErrorPage {
+    // the file under the root, served to the client accepts the html.
+    File(PathBuf),
+    Handler(ErrorHandler),
+}
+
+impl fmt::Debug for ErrorPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorPage::File(path) => f.debug_tuple("File").field(path).finish(),
+            ErrorPage::Handler(_) => f.write_str("Handler"),
+        }
+    }
+}
+
+/// the format of the error body negotiated by the `Accept`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorFormat {
+    Html,
+    Json,
+    Text,
+}
+
+impl ErrorFormat {
+    /// select the format with the highest quality, the html is preferred for the same quality.
+    pub(crate) fn negotiate(accept: Option<&str>) -> Self {
+        let accept = match accept {
+            Some(accept) if !accept.trim().is_empty() => accept,
+            _ => return ErrorFormat::Html,
+        };
+        let mut best = (0.0f32, None);
+        for item in accept.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let media = parts.next().unwrap_or("").to_ascii_lowercase();
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .map(|q| q.parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            let formats: &[ErrorFormat] = match media.as_str() {
+                "text/html" | "application/xhtml+xml" => &[ErrorFormat::Html],
+                "application/problem+json" | "application/json" => &[ErrorFormat::Json],
+                "text/plain" => &[ErrorFormat::Text],
+                "text/*" => &[ErrorFormat::Html, ErrorFormat::Text],
+                "*/*" => &[ErrorFormat::Html],
+                _ => &[],
+            };
+            for format in formats {
+                let is_better = match best {
+                    (_, None) => q > 0.0,
+                    (best_q, Some(best_format)) => {
+                        q > best_q || (q == best_q && (*format as u8) < (best_format as u8))
+                    }
+                };
+                if is_better {
+                    best = (q, Some(*format));
+                }
+            }
+        }
+        best.1.unwrap_or(ErrorFormat::Text)
+    }
+}
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// render the error body, the json is the problem details of the RFC 9457.
+pub(crate) fn render(
+    status: StatusCode,
+    format: ErrorFormat,
+    instance: &str,
+) -> (&'static str, String) {
+    let code = status.as_u16();
+    let title = status.canonical_reason().unwrap_or("Error");
+    match format {
+        ErrorFormat::Html => (
+            "text/html; charset=utf-8",
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{code} {title}</title></head>\n\
+                 <body>\n<h1>{code} {title}</h1>\n<p>{}</p>\n</body>\n</html>\n",
+                escape_html(instance)
+            ),
+        ),
+        ErrorFormat::Json => (
+            PROBLEM_JSON,
+            format!(
+                "{{\"type\":\"about:blank\",\"title\":\"{title}\",\"status\":{code},\"instance\":\"{}\"}}",
+                escape_json(instance)
+            ),
+        ),
+        ErrorFormat::Text => ("text/plain; charset=utf-8", format!("{code} {title}\n")),
+    }
+}
+
+/// replace the body of the error response, the headers of the response are kept.
+pub(crate) fn with_body(
+    mut resp: Response<Body>,
+    content_type: HeaderValue,
+    body: Body,
+    length: u64,
+) -> Response<Body> {
+    let headers = resp.headers_mut();
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(header::CONTENT_LENGTH, length.into());
+    headers.append(header::VARY, HeaderValue::from_static("Accept"));
+    *resp.body_mut() = body;
+    resp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{body_string, call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_negotiate() {
+        let negotiate = |accept| ErrorFormat::negotiate(Some(accept));
+        assert_eq!(
+            negotiate("text/html,application/xhtml+xml,*/*;q=0.8"),
+            ErrorFormat::Html
+        );
+        assert_eq!(negotiate("application/json"), ErrorFormat::Json);
+        assert_eq!(
+            negotiate("text/plain, application/json;q=0.5"),
+            ErrorFormat::Text
+        );
+        assert_eq!(negotiate("*/*"), ErrorFormat::Html);
+        assert_eq!(negotiate("image/png"), ErrorFormat::Text);
+        assert_eq!(ErrorFormat::negotiate(None), ErrorFormat::Html);
+        let (content_type, body) = render(StatusCode::NOT_FOUND, ErrorFormat::Json, "/a\"b");
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(
+            body,
+            r#"{"type":"about:blank","title":"Not Found","status":404,"instance":"/a\"b"}"#
+        );
+    }
+
+    #[test]
+    fn test_error_pages() {
+        let root = TempRoot::new("error");
+        root.mkdir("dir").write("404.html", "<h1>gone</h1>");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .error_page(StatusCode::NOT_FOUND, "/404.html")
+            .error_handler(StatusCode::FORBIDDEN, |ctx| {
+                let mut resp = Response::new(Body::Bytes(ctx.uri.path().to_string().into()));
+                *resp.status_mut() = ctx.status;
+                resp
+            })
+            .build();
+        runtime().block_on(async {
+            let get = |path: &str, accept: &str| request("GET", path, &[("accept", accept)], "");
+            let resp = call(&mut svc, get("/missing", "text/html")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            assert_eq!(body_string(resp).await, "<h1>gone</h1>");
+            let resp = call(&mut svc, get("/missing", "application/json")).await;
+            assert_eq!(
+                resp.headers()[header::CONTENT_TYPE],
+                "application/problem+json"
+            );
+            let resp = call(&mut svc, get("/dir", "text/html")).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            assert_eq!(body_string(resp).await, "/dir");
+        });
+    }
+}
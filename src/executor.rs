@@ -0,0 +1,173 @@
+use std::{
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_channel::oneshot;
+
+use crate::{
+    error::JoinError,
+    file::{BlockingFileReader, BoxFileReader},
+};
+
+/// The task of the blocking file operation.
+pub type BlockingTask = Box<dyn FnOnce() + Send + 'static>;
+
+/// The executor shared by the openers and the readers.
+pub type SharedExecutor = Arc<dyn BlockingExecutor>;
+
+/// The executor run the blocking file operations out of the async runtime,
+/// implement it to run the files on smol, async-std or the custom executor.
+pub trait BlockingExecutor: Send + Sync + 'static {
+    /// run the task in the thread which is allowed to block.
+    fn execute(&self, task: BlockingTask);
+
+    /// wrap the opened file as the reader, the default reader read the file with the executor.
+    fn file_reader(self: Arc<Self>, file: std::fs::File) -> BoxFileReader {
+        Box::new(BlockingFileReader::new(file, self))
+    }
+}
+
+/// The executor run every task in the new thread, work without any async runtime.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadExecutor;
+
+impl BlockingExecutor for ThreadExecutor {
+    fn execute(&self, task: BlockingTask) {
+        std::thread::spawn(task);
+    }
+}
+
+/// The executor run the tasks in the tokio blocking threads, the file is read by the tokio file.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioExecutor;
+
+#[cfg(feature = "tokio")]
+impl BlockingExecutor for TokioExecutor {
+    fn execute(&self, task: BlockingTask) {
+        tokio::task::spawn_blocking(task);
+    }
+
+    fn file_reader(self: Arc<Self>, file: std::fs::File) -> BoxFileReader {
+        Box::new(crate::file::TokioFileReader::new(
+            tokio::fs::File::from_std(file),
+        ))
+    }
+}
+
+/// the tokio executor if the `tokio` feature is enabled, otherwise the thread executor.
+pub fn default_executor() -> SharedExecutor {
+    #[cfg(feature = "tokio")]
+    return Arc::new(TokioExecutor);
+    #[cfg(not(feature = "tokio"))]
+    return Arc::new(ThreadExecutor);
+}
+
+/// The future of the function running in the blocking thread.
+pub struct BlockingFuture<T> {
+    // None mean the operation is unsupported.
+    inner: Option<oneshot::Receiver<Result<T>>>,
+}
+
+impl<T: Send + 'static> BlockingFuture<T> {
+    /// run the function with the executor, the custom opener implement the `read_dir` by it.
+    pub fn spawn<E, F>(executor: &E, f: F) -> Self
+    where
+        E: BlockingExecutor + ?Sized,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        executor.execute(Box::new(move || {
+            let _ = tx.send(f());
+        }));
+        Self { inner: Some(rx) }
+    }
+
+    /// the future which is completed with the result, e.g. the listing is already in memory.
+    pub fn ready(result: Result<T>) -> Self {
+        let (tx, rx) = oneshot::channel();
+        let _ = tx.send(result);
+        Self { inner: Some(rx) }
+    }
+
+    /// the future which is failed with the `ErrorKind::Unsupported`.
+    pub fn unsupported() -> Self {
+        Self { inner: None }
+    }
+}
+
+impl<T> Future for BlockingFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = match self.inner {
+            Some(ref mut inner) => inner,
+            None => return Poll::Ready(Err(Error::from(ErrorKind::Unsupported))),
+        };
+        // the result is Result<Result<T>>
+        // Poll::Ready(Ok(r)) => Poll::Ready(r) mean return the Poll::Ready(Ok) or Poll::Ready(Err), flatten
+        let p = Pin::new(inner).poll(cx);
+        match p {
+            Poll::Ready(Ok(r)) => Poll::Ready(r),
+            Poll::Ready(Err(_)) => {
+                //only the task dropped by the executor.
+                Poll::Ready(Err(Error::other(JoinError)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FileService, LocalFileReaderOpener};
+    use hyper::{header, service::Service, Request, StatusCode};
+
+    #[test]
+    fn test_thread_executor() {
+        let opener = LocalFileReaderOpener::new(".").executor(ThreadExecutor);
+        let mut svc = FileService::with_opener(opener);
+        let req = Request::get("/Cargo.toml")
+            .header(header::RANGE, "bytes=1-7")
+            .body(hyper::Body::empty())
+            .unwrap();
+        // the current thread runtime only drive the futures, the files are read in the threads.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let resp = svc.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(&body[..], b"package");
+        });
+    }
+
+    #[test]
+    fn test_blocking_future() {
+        use crate::{DirEntry, ReadDirFuture};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let entry = DirEntry {
+                name: "a.txt".into(),
+                is_dir: false,
+                size: 1,
+                modified: None,
+            };
+            let entries = ReadDirFuture::ready(Ok(vec![entry])).await.unwrap();
+            assert_eq!(entries[0].name, "a.txt");
+            let spawned = ReadDirFuture::spawn(&ThreadExecutor, || Ok(Vec::new())).await;
+            assert!(spawned.unwrap().is_empty());
+            let err = ReadDirFuture::unsupported().await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Unsupported);
+        });
+    }
+}
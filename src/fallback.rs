@@ -0,0 +1,466 @@
+//! The tower integration, the `FileServiceLayer` serves the files in front of the inner
+//! service and calls the inner service when no file is served, like the `ServeDir` fallback.
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    body::{Bytes, HttpBody},
+    header, HeaderMap, Method, Request, Response, StatusCode,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{
+    body::Body,
+    error::BoxError,
+    file::{FileReaderOpener, LocalFileReaderOpener},
+    filesvr::{Dispatched, FileService},
+};
+
+/// The body of the file or the inner service.
+pub enum FallbackBody<B> {
+    File(Body),
+    Fallback(B),
+}
+
+impl<B> HttpBody for FallbackBody<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match *self.get_mut() {
+            FallbackBody::File(ref mut b) => Pin::new(b).poll_data(cx).map_err(Into::into),
+            FallbackBody::Fallback(ref mut b) => Pin::new(b).poll_data(cx).map_err(Into::into),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match *self.get_mut() {
+            FallbackBody::File(ref mut b) => Pin::new(b).poll_trailers(cx).map_err(Into::into),
+            FallbackBody::Fallback(ref mut b) => Pin::new(b).poll_trailers(cx).map_err(Into::into),
+        }
+    }
+}
+
+fn internal_error() -> Response<Body> {
+    let mut resp = Response::new(Body::Empty);
+    *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    resp
+}
+
+/// The service serves the files first, the request of the missing file or the method not allowed
+/// by the files is passed to the inner service. The file errors are mapped by the error hook or
+/// responded as `500 Internal Server Error`, so the error is the error of the inner service, e.g.
+/// `Infallible` for the axum fallback.
+pub struct Fallback<S, O = LocalFileReaderOpener> {
+    files: FileService<O>,
+    inner: S,
+}
+
+impl<S: Clone, O> Clone for Fallback<S, O> {
+    fn clone(&self) -> Self {
+        Self {
+            files: self.files.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S, O: FileReaderOpener> Fallback<S, O> {
+    pub fn new(files: FileService<O>, inner: S) -> Self {
+        Self { files, inner }
+    }
+}
+
+impl<S, O, B, ResB> Service<Request<B>> for Fallback<S, O>
+where
+    O: FileReaderOpener,
+    S: Service<Request<B>, Response = Response<ResB>> + Clone + Send + 'static,
+    S::Future: Send,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<FallbackBody<ResB>>;
+
+    type Error = S::Error;
+
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let files = self.files.clone();
+        // take the inner service which is ready.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        // the `OPTIONS` given back is answered by the inner service without the CORS of the files.
+        let origin = match request.method() {
+            &Method::OPTIONS => None,
+            _ => request.headers().get(header::ORIGIN).cloned(),
+        };
+        Box::pin(async move {
+            let resp = match files.clone().handle(request, true).await {
+                Ok(Dispatched::Response(resp)) => resp,
+                Ok(Dispatched::Fallthrough(request)) => {
+                    let mut resp = inner.call(request).await?;
+                    files.apply_cors(origin.as_ref(), resp.headers_mut());
+                    return Ok(resp.map(FallbackBody::Fallback));
+                }
+                Err(_) => internal_error(),
+            };
+            Ok(resp.map(FallbackBody::File))
+        })
+    }
+}
+
+/// The layer wraps the inner service with the `Fallback`.
+pub struct FileServiceLayer<O = LocalFileReaderOpener> {
+    files: FileService<O>,
+}
+
+impl<O> Clone for FileServiceLayer<O> {
+    fn clone(&self) -> Self {
+        Self {
+            files: self.files.clone(),
+        }
+    }
+}
+
+impl FileServiceLayer {
+    pub fn new(root: impl Into<String>) -> Self {
+        Self::with_service(FileService::new(root))
+    }
+}
+
+impl<O: FileReaderOpener> FileServiceLayer<O> {
+    pub fn with_service(files: FileService<O>) -> Self {
+        Self { files }
+    }
+}
+
+impl<S, O: FileReaderOpener> Layer<S> for FileServiceLayer<O> {
+    type Service = Fallback<S, O>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Fallback::new(self.files.clone(), inner)
+    }
+}
+
+/// The service always responds `404 Not Found`, use it as the inner service of the
+/// `Fallback` when the files are the last service, e.g. the fallback service of the axum router.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NotFound;
+
+impl<B> Service<Request<B>> for NotFound {
+    type Response = Response<Body>;
+
+    type Error = Infallible;
+
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: Request<B>) -> Self::Future {
+        let mut resp = Response::new(Body::Empty);
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        std::future::ready(Ok(resp))
+    }
+}
+
+#[cfg(feature = "hyper1")]
+mod http1_impl {
+    use super::*;
+    use crate::hyper1::{
+        merge_headers_to_http1, request_from_http1, request_head_from_http1, response_to_http1,
+    };
+    use http_body1::{Body as HttpBody1, Frame, SizeHint};
+
+    impl<B> HttpBody1 for FallbackBody<B>
+    where
+        B: HttpBody1<Data = Bytes> + Unpin,
+        B::Error: Into<BoxError>,
+    {
+        type Data = Bytes;
+
+        type Error = BoxError;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            match *self.get_mut() {
+                FallbackBody::File(ref mut b) => Pin::new(b).poll_frame(cx).map_err(Into::into),
+                FallbackBody::Fallback(ref mut b) => Pin::new(b).poll_frame(cx).map_err(Into::into),
+            }
+        }
+
+        fn is_end_stream(&self) -> bool {
+            match *self {
+                FallbackBody::File(ref b) => HttpBody1::is_end_stream(b),
+                FallbackBody::Fallback(ref b) => b.is_end_stream(),
+            }
+        }
+
+        fn size_hint(&self) -> SizeHint {
+            match *self {
+                FallbackBody::File(ref b) => HttpBody1::size_hint(b),
+                FallbackBody::Fallback(ref b) => b.size_hint(),
+            }
+        }
+    }
+
+    impl<O, B> Service<http1::Request<B>> for FileService<O>
+    where
+        O: FileReaderOpener,
+        B: HttpBody1 + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        type Response = http1::Response<Body>;
+
+        type Error = crate::Error;
+
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http1::Request<B>) -> Self::Future {
+            hyper1::service::Service::call(self, request)
+        }
+    }
+
+    impl<S, O, B, ResB> Service<http1::Request<B>> for Fallback<S, O>
+    where
+        O: FileReaderOpener,
+        S: Service<http1::Request<B>, Response = http1::Response<ResB>> + Clone + Send + 'static,
+        S::Future: Send,
+        B: HttpBody1 + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        type Response = http1::Response<FallbackBody<ResB>>;
+
+        type Error = S::Error;
+
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: http1::Request<B>) -> Self::Future {
+            let files = self.files.clone();
+            let clone = self.inner.clone();
+            let mut inner = std::mem::replace(&mut self.inner, clone);
+            Box::pin(async move {
+                let head = match request_head_from_http1(&request) {
+                    Ok(head) => head,
+                    Err(_) => {
+                        let resp = inner.call(request).await?;
+                        return Ok(resp.map(FallbackBody::Fallback));
+                    }
+                };
+                let resp = if files.takes_body(&head) {
+                    // the request with the body is never given back.
+                    match request_from_http1(request) {
+                        Ok(request) => match files.handle(request, true).await {
+                            Ok(Dispatched::Response(resp)) => Some(resp),
+                            _ => None,
+                        },
+                        Err(_) => None,
+                    }
+                } else {
+                    // the file is served by the head, the request is kept for the inner service.
+                    let origin = match head.method() {
+                        &Method::OPTIONS => None,
+                        _ => head.headers().get(header::ORIGIN).cloned(),
+                    };
+                    let head = head.map(|_| hyper::Body::empty());
+                    match files.clone().handle(head, true).await {
+                        Ok(Dispatched::Response(resp)) => Some(resp),
+                        Ok(Dispatched::Fallthrough(_)) => {
+                            let mut resp = inner.call(request).await?;
+                            let mut headers = HeaderMap::new();
+                            files.apply_cors(origin.as_ref(), &mut headers);
+                            merge_headers_to_http1(&headers, resp.headers_mut());
+                            return Ok(resp.map(FallbackBody::Fallback));
+                        }
+                        Err(_) => None,
+                    }
+                };
+                let resp = resp.unwrap_or_else(internal_error);
+                let resp = response_to_http1(resp).unwrap_or_else(|_| {
+                    let mut resp = http1::Response::new(Body::Empty);
+                    *resp.status_mut() = http1::StatusCode::INTERNAL_SERVER_ERROR;
+                    resp
+                });
+                Ok(resp.map(FallbackBody::File))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+    use hyper::{service::service_fn, Body as HyperBody, Method};
+
+    #[test]
+    fn test_fallback() {
+        let inner = service_fn(|_req: Request<HyperBody>| async {
+            Ok::<_, Infallible>(Response::new(HyperBody::from("fallback")))
+        });
+        let mut svc = FileServiceLayer::new(".").layer(inner);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut call = |method: Method, path: &str| {
+                let req = Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .body(HyperBody::empty())
+                    .unwrap();
+                svc.call(req)
+            };
+            let resp = call(Method::GET, "/Cargo.toml").await.unwrap();
+            assert!(matches!(resp.body(), FallbackBody::File(_)));
+            let resp = call(Method::GET, "/missing").await.unwrap();
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(&body[..], b"fallback");
+            let resp = call(Method::POST, "/Cargo.toml").await.unwrap();
+            assert!(matches!(resp.body(), FallbackBody::Fallback(_)));
+        });
+    }
+
+    #[test]
+    fn test_fallback_write() {
+        let root = TempRoot::new("fallback");
+        let files = FileServiceBuilder::new(root.root()).write(true).build();
+        let inner = service_fn(|_req: Request<HyperBody>| async {
+            Ok::<_, Infallible>(Response::new(HyperBody::from("fallback")))
+        });
+        let mut svc = FileServiceLayer::with_service(files).layer(inner);
+        runtime().block_on(async {
+            let resp = svc
+                .call(request("PUT", "/a.txt", &[], "hello"))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert!(matches!(resp.body(), FallbackBody::File(_)));
+            let resp = svc.call(request("GET", "/a.txt", &[], "")).await.unwrap();
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(&body[..], b"hello");
+            let resp = svc
+                .call(request("DELETE", "/missing", &[], ""))
+                .await
+                .unwrap();
+            assert!(matches!(resp.body(), FallbackBody::Fallback(_)));
+            let resp = svc
+                .call(request("DELETE", "/a.txt", &[], ""))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        });
+        assert!(!root.join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_fallback_options() {
+        let root = TempRoot::new("fallback-options");
+        root.write("a.txt", "a");
+        let router = service_fn(|req: Request<HyperBody>| async move {
+            let resp = match (req.method(), req.uri().path()) {
+                (&Method::OPTIONS, "/api") => Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .header(header::ALLOW, "GET, POST")
+                    .body(HyperBody::empty()),
+                _ => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(HyperBody::empty()),
+            };
+            Ok::<_, Infallible>(resp.unwrap())
+        });
+        let mut svc = FileServiceLayer::new(root.root()).layer(router);
+        runtime().block_on(async {
+            let resp = svc.call(request("OPTIONS", "/api", &[], "")).await;
+            let resp = resp.unwrap();
+            assert!(matches!(resp.body(), FallbackBody::Fallback(_)));
+            assert_eq!(resp.headers()[header::ALLOW], "GET, POST");
+            let resp = svc.call(request("OPTIONS", "/a.txt", &[], "")).await;
+            let resp = resp.unwrap();
+            assert!(matches!(resp.body(), FallbackBody::File(_)));
+            assert_eq!(resp.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+        });
+    }
+
+    #[cfg(feature = "cors")]
+    #[test]
+    fn test_fallback_cors() {
+        let root = TempRoot::new("fallback-cors");
+        root.write("a.txt", "a");
+        let files = FileServiceBuilder::new(root.root())
+            .cors_origin("https://app.example.com")
+            .build();
+        let inner = service_fn(|_req: Request<HyperBody>| async {
+            Ok::<_, Infallible>(Response::new(HyperBody::from("fallback")))
+        });
+        let mut svc = FileServiceLayer::with_service(files).layer(inner);
+        runtime().block_on(async {
+            let origin = ("origin", "https://app.example.com");
+            let preflight = [origin, ("access-control-request-method", "GET")];
+            let req = request("OPTIONS", "/a.txt", &preflight, "");
+            let resp = svc.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert!(matches!(resp.body(), FallbackBody::File(_)));
+            // the preflight of the missing path is answered by the inner service.
+            let resp = svc.call(request("OPTIONS", "/api", &preflight, "")).await;
+            let resp = resp.unwrap();
+            assert!(matches!(resp.body(), FallbackBody::Fallback(_)));
+            assert!(!resp
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+            // the files and the inner service have the same CORS headers.
+            for uri in ["/a.txt", "/api"] {
+                let resp = svc.call(request("GET", uri, &[origin], "")).await.unwrap();
+                let headers = resp.headers();
+                assert_eq!(
+                    headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                    "https://app.example.com"
+                );
+                assert_eq!(headers[header::VARY], "Origin");
+            }
+            let resp = svc.call(request("GET", "/api", &[], "")).await.unwrap();
+            assert!(matches!(resp.body(), FallbackBody::Fallback(_)));
+            assert!(!resp
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        });
+    }
+}
@@ -1,64 +1,205 @@
 use std::{
-    cmp::min,
-    fs::{OpenOptions, Permissions},
+    fmt,
+    fs::{File, Metadata, OpenOptions, Permissions},
     future::Future,
-    io::SeekFrom,
-    io::{Error, ErrorKind, Result},
-    mem::MaybeUninit,
+    io::{Error, Read, Result, Seek, SeekFrom},
     path::{Path, PathBuf},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
-    time::SystemTime,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use hyper::body::Bytes;
-use tokio::{
-    fs::File,
-    io::{AsyncRead, AsyncSeek, ReadBuf},
-    task::JoinHandle,
-};
 
+use crate::executor::{default_executor, BlockingExecutor, BlockingFuture, SharedExecutor};
+
+#[cfg(feature = "tokio")]
 const READ_BUF_SIZE: usize = 4 * 1024;
+// the read of the blocking reader hop threads, read more each time.
+const BLOCKING_READ_BUF_SIZE: usize = 64 * 1024;
 
 /// file with the meta use for body stream.
-#[derive(Debug)]
 pub struct FileWithMeta {
     pub size: u64,
-    pub file: File,
+    pub reader: BoxFileReader,
+    pub is_dir: bool,
+    pub modified: Option<SystemTime>,
+    /// the permissions of the local file, the remote object has none.
+    pub permisions: Option<Permissions>,
+    /// the strong entity tag of the content, if the backend knows one.
+    pub etag: Option<String>,
+    /// the content behind the path never changes, e.g. content addressed blobs.
+    pub immutable: bool,
+}
+
+impl FileWithMeta {
+    /// open the file and read the meta info, should be called in the blocking thread.
+    pub(crate) fn open_blocking(path: impl AsRef<Path>, executor: &SharedExecutor) -> Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        Self::from_std(file, executor)
+    }
+
+    /// wrap the opened std file with the meta info, should be called in the blocking thread.
+    pub(crate) fn from_std(file: File, executor: &SharedExecutor) -> Result<Self> {
+        let meta = file.metadata()?;
+        let etag = local_etag(&meta);
+        Ok(FileWithMeta {
+            reader: executor.clone().file_reader(file),
+            size: meta.len(),
+            is_dir: meta.is_dir(),
+            modified: meta.modified().ok(),
+            permisions: Some(meta.permissions()),
+            etag,
+            immutable: false,
+        })
+    }
+}
+
+/// the strong entity tag of the local file by the size and the modified time in nanoseconds, the
+/// write renames the new file into place so every write changes the tag.
+pub(crate) fn local_etag(meta: &Metadata) -> Option<String> {
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("\"{:x}-{:x}\"", meta.len(), modified.as_nanos()))
+}
+
+impl fmt::Debug for FileWithMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileWithMeta")
+            .field("size", &self.size)
+            .field("is_dir", &self.is_dir)
+            .field("modified", &self.modified)
+            .field("permisions", &self.permisions)
+            .field("etag", &self.etag)
+            .field("immutable", &self.immutable)
+            .finish()
+    }
+}
+
+/// the entry of the directory listing.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
     pub is_dir: bool,
+    pub size: u64,
     pub modified: Option<SystemTime>,
-    pub permisions: Permissions,
+}
+
+impl DirEntry {
+    /// list the directory, should be called in the blocking thread.
+    pub(crate) fn read_dir_blocking(path: impl AsRef<Path>) -> Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            // follow the symlink, skip the broken one.
+            let meta = match std::fs::metadata(entry.path()) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            entries.push(DirEntry {
+                name,
+                is_dir: meta.is_dir(),
+                size: meta.len(),
+                modified: meta.modified().ok(),
+            });
+        }
+        Ok(entries)
+    }
 }
 
 /// The file reader which read the bytes from file to fill the body.
-pub trait FileReader: AsyncSeek + Unpin + Send + 'static {
+/// The seek is same as the `AsyncSeek` of tokio, but not bound to any async runtime.
+pub trait FileReader: Unpin + Send + 'static {
     fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, readn: u64) -> Poll<Result<Bytes>>;
+
+    /// start the seek, the seek is completed by `poll_complete`.
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()>;
+
+    /// wait the seek completed, return the new position.
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u64>>;
+}
+
+/// The file reader of any backend.
+pub type BoxFileReader = Box<dyn FileReader>;
+
+impl FileReader for BoxFileReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, readn: u64) -> Poll<Result<Bytes>> {
+        Pin::new(&mut **self.get_mut()).poll_read(cx, readn)
+    }
+
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+        Pin::new(&mut **self.get_mut()).start_seek(position)
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u64>> {
+        Pin::new(&mut **self.get_mut()).poll_complete(cx)
+    }
+}
+
+impl From<FileWithMeta> for BoxFileReader {
+    fn from(val: FileWithMeta) -> Self {
+        val.reader
+    }
 }
 
 pub trait FileReaderOpener: Send + Sync + 'static {
     type Output: Into<FileWithMeta>;
 
-    type Future: Future<Output = Result<Self::Output>> + Unpin + 'static;
+    type Future: Future<Output = Result<Self::Output>> + Unpin + Send + 'static;
 
     fn open<T: AsRef<Path>>(&self, path: T) -> Self::Future;
+
+    /// list the entries of the directory, the backend without the directory returns `Unsupported`.
+    fn read_dir<T: AsRef<Path>>(&self, _path: T) -> ReadDirFuture {
+        ReadDirFuture::unsupported()
+    }
+
+    /// the opener select the root by the host, the first component of the path is the host
+    /// of the request, e.g. `example.com/index.html`, or `_` for the request without the host.
+    fn is_virtual_host(&self) -> bool {
+        false
+    }
+
+    /// the backend has the writable local files, the write methods are enabled.
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    /// the local path of the file for the write methods, should be called in the blocking thread.
+    /// the backend without the writable local files returns none.
+    fn local_path(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// the executor run the blocking write operations.
+    fn blocking_executor(&self) -> SharedExecutor {
+        default_executor()
+    }
 }
 
 /// The file reader which read the bytes from file to fill the body.
 /// Using th tokio file in tokio async runtime.
+#[cfg(feature = "tokio")]
 pub struct TokioFileReader {
     file: tokio::fs::File,
-    buf: Box<[MaybeUninit<u8>; READ_BUF_SIZE]>,
+    buf: Box<[std::mem::MaybeUninit<u8>; READ_BUF_SIZE]>,
 }
 
+#[cfg(feature = "tokio")]
 impl TokioFileReader {
-    fn new(file: File) -> Self {
+    pub(crate) fn new(file: tokio::fs::File) -> Self {
         Self {
             file,
-            buf: Box::new([MaybeUninit::uninit(); READ_BUF_SIZE]),
+            buf: Box::new([std::mem::MaybeUninit::uninit(); READ_BUF_SIZE]),
         }
     }
 }
 
+#[cfg(feature = "tokio")]
 impl FileReader for TokioFileReader {
     /// read bytes from file to fill the http body.
     fn poll_read(
@@ -70,9 +211,9 @@ impl FileReader for TokioFileReader {
             ref mut file,
             ref mut buf,
         } = *self;
-        let buf_len = min(readn as usize, buf.len());
-        let mut buf = ReadBuf::uninit(&mut buf[..buf_len]);
-        match Pin::new(file).poll_read(cx, &mut buf) {
+        let buf_len = std::cmp::min(readn as usize, buf.len());
+        let mut buf = tokio::io::ReadBuf::uninit(&mut buf[..buf_len]);
+        match tokio::io::AsyncRead::poll_read(Pin::new(file), cx, &mut buf) {
             Poll::Ready(Ok(())) => {
                 let bs = buf.filled();
                 if bs.is_empty() {
@@ -86,79 +227,164 @@ impl FileReader for TokioFileReader {
             Poll::Pending => Poll::Pending,
         }
     }
-}
 
-impl AsyncSeek for TokioFileReader {
     fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
-        Pin::new(&mut self.get_mut().file).start_seek(position)
+        tokio::io::AsyncSeek::start_seek(Pin::new(&mut self.get_mut().file), position)
     }
 
     fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u64>> {
-        Pin::new(&mut self.get_mut().file).poll_complete(cx)
+        tokio::io::AsyncSeek::poll_complete(Pin::new(&mut self.get_mut().file), cx)
     }
 }
 
-impl From<FileWithMeta> for TokioFileReader {
-    fn from(val: FileWithMeta) -> Self {
-        TokioFileReader::new(val.file)
-    }
+enum BlockingState {
+    Idle(File),
+    Reading(BlockingFuture<(File, Result<Bytes>)>),
+    Seeking(BlockingFuture<(File, Result<u64>)>),
+    // the file is lost with the dropped task.
+    Closed,
 }
 
-/// The future get the file and meta info
-pub struct FileWithMetaFuture {
-    inner: JoinHandle<Result<FileWithMeta>>,
-}
-
-impl FileWithMetaFuture {
-    fn new(path: PathBuf) -> Self {
-        let inner = tokio::task::spawn_blocking(move || -> Result<FileWithMeta> {
-            let file = OpenOptions::new().read(true).open(path)?;
-            let meta = file.metadata()?;
-            let file = tokio::fs::File::from_std(file);
-            Ok(FileWithMeta {
-                file,
-                size: meta.len(),
-                is_dir: meta.is_dir(),
-                modified: meta.modified().ok(),
-                permisions: meta.permissions(),
-            })
-        });
-        Self { inner }
+/// The file reader which read the std file with the blocking executor,
+/// work with any async runtime.
+pub struct BlockingFileReader<E: ?Sized = dyn BlockingExecutor> {
+    executor: Arc<E>,
+    state: BlockingState,
+    pos: u64,
+}
+
+impl<E: BlockingExecutor + ?Sized> BlockingFileReader<E> {
+    pub fn new(file: File, executor: Arc<E>) -> Self {
+        Self {
+            executor,
+            state: BlockingState::Idle(file),
+            pos: 0,
+        }
+    }
+
+    fn closed_error() -> Error {
+        Error::other("file is closed by the executor.")
     }
 }
 
-impl Future for FileWithMetaFuture {
-    type Output = Result<FileWithMeta>;
+impl<E: BlockingExecutor + ?Sized> FileReader for BlockingFileReader<E> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, readn: u64) -> Poll<Result<Bytes>> {
+        let this = self.get_mut();
+        loop {
+            match std::mem::replace(&mut this.state, BlockingState::Closed) {
+                BlockingState::Idle(mut file) => {
+                    let buf_len = readn.min(BLOCKING_READ_BUF_SIZE as u64) as usize;
+                    let fut = BlockingFuture::spawn(&*this.executor, move || {
+                        let mut buf = vec![0u8; buf_len];
+                        let rs = file.read(&mut buf).map(|n| {
+                            buf.truncate(n);
+                            Bytes::from(buf)
+                        });
+                        Ok((file, rs))
+                    });
+                    this.state = BlockingState::Reading(fut);
+                }
+                BlockingState::Reading(mut fut) => match Pin::new(&mut fut).poll(cx) {
+                    Poll::Ready(Ok((file, rs))) => {
+                        this.state = BlockingState::Idle(file);
+                        if let Ok(ref bs) = rs {
+                            this.pos += bs.len() as u64;
+                        }
+                        return Poll::Ready(rs);
+                    }
+                    Poll::Ready(Err(_)) => return Poll::Ready(Err(Self::closed_error())),
+                    Poll::Pending => {
+                        this.state = BlockingState::Reading(fut);
+                        return Poll::Pending;
+                    }
+                },
+                BlockingState::Seeking(fut) => {
+                    // complete the seek before read.
+                    this.state = BlockingState::Seeking(fut);
+                    if let Err(e) = std::task::ready!(Pin::new(&mut *this).poll_complete(cx)) {
+                        return Poll::Ready(Err(e));
+                    }
+                }
+                BlockingState::Closed => return Poll::Ready(Err(Self::closed_error())),
+            }
+        }
+    }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        // the result is Result<Result<FileWithMeta>>
-        // Poll::Ready(Ok(r)) => Poll::Ready(r) mean return the Poll::Ready(Ok) or Poll::Ready(Err), flatten
-        let p = Pin::new(&mut self.inner).poll(cx);
-        match p {
-            Poll::Ready(Ok(r)) => Poll::Ready(r),
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+        let this = self.get_mut();
+        match std::mem::replace(&mut this.state, BlockingState::Closed) {
+            BlockingState::Idle(mut file) => {
+                let fut = BlockingFuture::spawn(&*this.executor, move || {
+                    let rs = file.seek(position);
+                    Ok((file, rs))
+                });
+                this.state = BlockingState::Seeking(fut);
+                Ok(())
+            }
+            BlockingState::Closed => Err(Self::closed_error()),
+            state => {
+                this.state = state;
+                Err(Error::other("other file operation is pending."))
+            }
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u64>> {
+        let this = self.get_mut();
+        let fut = match this.state {
+            BlockingState::Seeking(ref mut fut) => fut,
+            BlockingState::Closed => return Poll::Ready(Err(Self::closed_error())),
+            _ => return Poll::Ready(Ok(this.pos)),
+        };
+        match Pin::new(fut).poll(cx) {
+            Poll::Ready(Ok((file, rs))) => {
+                this.state = BlockingState::Idle(file);
+                if let Ok(pos) = rs {
+                    this.pos = pos;
+                }
+                Poll::Ready(rs)
+            }
             Poll::Ready(Err(_)) => {
-                //only Joinhandle error.
-                Poll::Ready(Err(Error::new(
-                    ErrorKind::Other,
-                    "error execute in background.",
-                )))
+                this.state = BlockingState::Closed;
+                Poll::Ready(Err(Self::closed_error()))
             }
             Poll::Pending => Poll::Pending,
         }
     }
 }
 
-pub struct TokioFileReaderOpener {
+/// The future get the file and meta info
+pub type FileWithMetaFuture = BlockingFuture<FileWithMeta>;
+
+/// The future get the entries of the directory.
+pub type ReadDirFuture = BlockingFuture<Vec<DirEntry>>;
+
+/// The opener of the local files under the root, the files are opened with the executor.
+pub struct LocalFileReaderOpener {
     root: PathBuf,
+    executor: SharedExecutor,
 }
 
-impl TokioFileReaderOpener {
+/// The opener of the local files with the tokio executor.
+#[cfg(feature = "tokio")]
+pub type TokioFileReaderOpener = LocalFileReaderOpener;
+
+impl LocalFileReaderOpener {
     pub fn new(root: impl Into<PathBuf>) -> Self {
-        Self { root: root.into() }
+        Self {
+            root: root.into(),
+            executor: default_executor(),
+        }
+    }
+
+    /// the executor run the blocking file operations, default is the tokio executor.
+    pub fn executor(mut self, executor: impl BlockingExecutor) -> Self {
+        self.executor = Arc::new(executor);
+        self
     }
 }
 
-impl FileReaderOpener for TokioFileReaderOpener {
+impl FileReaderOpener for LocalFileReaderOpener {
     type Output = FileWithMeta;
 
     type Future = FileWithMetaFuture;
@@ -166,6 +392,30 @@ impl FileReaderOpener for TokioFileReaderOpener {
     fn open<T: AsRef<Path>>(&self, path: T) -> Self::Future {
         let mut full_path = self.root.clone();
         full_path.extend(path.as_ref());
-        FileWithMetaFuture::new(full_path)
+        let executor = self.executor.clone();
+        FileWithMetaFuture::spawn(&*self.executor, move || {
+            FileWithMeta::open_blocking(full_path, &executor)
+        })
+    }
+
+    fn read_dir<T: AsRef<Path>>(&self, path: T) -> ReadDirFuture {
+        let mut full_path = self.root.clone();
+        full_path.extend(path.as_ref());
+        ReadDirFuture::spawn(&*self.executor, move || {
+            DirEntry::read_dir_blocking(full_path)
+        })
+    }
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        let mut full_path = self.root.clone();
+        full_path.extend(path);
+        Some(full_path)
+    }
+
+    fn blocking_executor(&self) -> SharedExecutor {
+        self.executor.clone()
     }
 }
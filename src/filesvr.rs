@@ -1,62 +1,459 @@
 use std::{
-    io::{Error, ErrorKind, Result},
+    path::PathBuf,
     pin::Pin,
     result::Result as StdResult,
+    sync::Arc,
     task::{Context, Poll},
 };
 
-use hyper::{service::Service, Request, Response, StatusCode};
+use hyper::{
+    body::HttpBody,
+    header::{self, HeaderValue},
+    service::Service,
+    HeaderMap, Method, Request, Response, StatusCode,
+};
 
 use std::future::Future;
 
+#[cfg(feature = "archive")]
+use crate::archive;
+#[cfg(feature = "cors")]
+use crate::cors;
+#[cfg(feature = "tus")]
+use crate::tus;
+#[cfg(feature = "webdav")]
+use crate::webdav;
 use crate::{
-    body::Body,
-    request_resolve::{RequestResolve, Resolved},
+    body::{Body, FileBytesStream},
+    config::Config,
+    disposition::disposition,
+    error::{BoxError, Error},
+    error_page::{self, ErrorContext, ErrorFormat, ErrorPage},
+    file::{FileReaderOpener, FileWithMeta, LocalFileReaderOpener},
+    form,
+    headers::{self, PathHeaders},
+    listing,
+    mount::MountFileReaderOpener,
+    overlay::OverlayFileReaderOpener,
+    request_resolve::{self, decode_percents, host_path, ResolveFuture, Resolved},
     resp_builder::ResponseBuilder,
+    write,
 };
 
-#[derive(Clone)]
-pub struct FileService {
-    local_root: String,
+const NO_CACHE: &str = "no-cache";
+
+type Result<T> = StdResult<T, Error>;
+
+/// The response of the dispatch, or the request given back to the tower fallback.
+pub(crate) enum Dispatched<B> {
+    Response(Response<Body>),
+    Fallthrough(Request<B>),
+}
+
+/// The future of the response with the rendered error body.
+pub(crate) type ErrorFuture = Pin<Box<dyn Future<Output = Response<Body>> + Send>>;
+
+pub struct FileService<O = LocalFileReaderOpener> {
+    opener: Arc<O>,
+    config: Arc<Config>,
+}
+
+impl<O> Clone for FileService<O> {
+    fn clone(&self) -> Self {
+        Self {
+            opener: self.opener.clone(),
+            config: self.config.clone(),
+        }
+    }
 }
 
 impl FileService {
     pub fn new(root: impl Into<String>) -> Self {
         let local_root = root.into();
-        Self { local_root }
+        Self::with_opener(LocalFileReaderOpener::new(local_root))
+    }
+}
+
+impl<O: FileReaderOpener> FileService<O> {
+    /// create the service which open the files with the opener.
+    pub fn with_opener(opener: O) -> Self {
+        Self::with_config(Arc::new(opener), Arc::default())
+    }
+
+    pub(crate) fn with_config(opener: Arc<O>, config: Arc<Config>) -> Self {
+        Self { opener, config }
+    }
+
+    /// resolve the file of the request, the request is not borrowed by the future.
+    pub(crate) fn resolve<B>(&self, request: &Request<B>) -> ResolveFuture {
+        request_resolve::resolve(&self.opener, &self.config, request)
+    }
+
+    /// resolve the path of the request as the `GET` of it, the request is not borrowed.
+    fn resolve_as_get<B>(&self, request: &Request<B>) -> ResolveFuture {
+        let mut get = Request::new(());
+        *get.method_mut() = Method::GET;
+        *get.uri_mut() = request.uri().clone();
+        *get.headers_mut() = request.headers().clone();
+        self.resolve(&get)
     }
 
-    async fn serv<B>(self, request: Request<B>) -> Result<Response<Body>> {
-        let resolved = RequestResolve::resolve(&self.local_root, &request).await?;
+    /// build the response of the resolved file.
+    pub(crate) fn respond<B>(
+        &self,
+        resolved: Resolved,
+        request: &Request<B>,
+        host_headers: &[PathHeaders],
+    ) -> Result<Response<Body>> {
         let resp = match resolved {
             Resolved::IsDirectory => Response::builder()
                 .status(StatusCode::FORBIDDEN)
                 .body(Body::Empty),
             Resolved::MethodNotMatched => Response::builder()
-                .status(StatusCode::BAD_REQUEST)
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(
+                    header::ALLOW,
+                    self.config.allow_header(self.opener.is_writable()),
+                )
                 .body(Body::Empty),
+            Resolved::Options => {
+                let builder = Response::builder().status(StatusCode::NO_CONTENT).header(
+                    header::ALLOW,
+                    self.config.allow_header(self.opener.is_writable()),
+                );
+                // the clients probe the `DAV` header before mounting.
+                #[cfg(feature = "webdav")]
+                let builder = match self.config.webdav {
+                    true => builder
+                        .header("dav", webdav::DAV_CLASS)
+                        .header("ms-author-via", "DAV"),
+                    false => builder,
+                };
+                builder.body(Body::Empty)
+            }
             Resolved::NotFound => Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(Body::Empty),
             Resolved::PermissionDenied => Response::builder()
                 .status(StatusCode::FORBIDDEN)
                 .body(Body::Empty),
-            Resolved::Found(f) => ResponseBuilder::new().request(&request).build(f),
+            Resolved::Unavailable => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::Empty),
+            Resolved::Redirect(location) => Response::builder()
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(header::LOCATION, location)
+                .body(Body::Empty),
+            Resolved::Listing(entries) => {
+                let html = listing::render(request.uri().path(), entries);
+                let builder = Response::builder()
+                    .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+                    .header(header::CONTENT_LENGTH, html.len());
+                if request.method() == hyper::Method::HEAD {
+                    builder.body(Body::Empty)
+                } else {
+                    builder.body(Body::Bytes(html.into()))
+                }
+            }
+            Resolved::Found(f) => {
+                let config = &self.config;
+                // the fallback page changes with the deployment, always revalidate it.
+                let mime = config.mime_type(&f.path);
+                let path = decode_percents(request.uri().path());
+                let cache_control = if f.is_spa_fallback {
+                    Some(HeaderValue::from_static(NO_CACHE))
+                } else {
+                    config.cache_policy(&path, mime)
+                };
+                let mut path_headers = HeaderMap::new();
+                headers::apply(&config.path_headers, &path, &mut path_headers);
+                headers::apply(host_headers, &path, &mut path_headers);
+                ResponseBuilder::new()
+                    .request(request)
+                    .content_type(mime)
+                    .content_disposition(disposition(config, &f.path, request))
+                    .content_encoding(f.encoding)
+                    .vary_encoding(!config.precompressed.is_empty())
+                    .cache_control(cache_control)
+                    .expires(config.expires)
+                    .max_ranges(config.max_ranges)
+                    .headers(path_headers)
+                    .build(f.file)
+            }
+        };
+        let mut resp = resp?;
+        self.config.apply_headers(resp.headers_mut());
+        Ok(resp)
+    }
+
+    /// render the body of the error response with the error page, the request is not borrowed
+    /// by the future. the response with the body or not the error is returned as it is.
+    pub(crate) fn render_error<B>(
+        &self,
+        resp: Response<Body>,
+        request: &Request<B>,
+    ) -> ErrorFuture {
+        let status = resp.status();
+        let config = &self.config;
+        let is_error = status.is_client_error() || status.is_server_error();
+        if !is_error || !matches!(resp.body(), Body::Empty) {
+            return Box::pin(std::future::ready(resp));
+        }
+        let page = config.error_pages.get(&status);
+        if page.is_none() && !config.error_bodies {
+            return Box::pin(std::future::ready(resp));
+        }
+        if let Some(ErrorPage::Handler(handler)) = page {
+            let ctx = ErrorContext {
+                status,
+                method: request.method(),
+                uri: request.uri(),
+                headers: request.headers(),
+            };
+            return Box::pin(std::future::ready(handler(&ctx)));
+        }
+        let accept = request.headers().get(header::ACCEPT);
+        let format = ErrorFormat::negotiate(accept.and_then(|v| v.to_str().ok()));
+        let is_head = request.method() == hyper::Method::HEAD;
+        let instance = request.uri().path().to_string();
+        let page_path = match page {
+            Some(ErrorPage::File(path)) if format == ErrorFormat::Html => {
+                Some(host_path(&*self.opener, request, path))
+            }
+            _ => None,
         };
-        let resp = match resp {
+        let opener = self.opener.clone();
+        let config = config.clone();
+        Box::pin(async move {
+            if let Some(path) = page_path {
+                let page = opener.open(&path).await.map(Into::<FileWithMeta>::into);
+                if let Some(page) = page.ok().filter(|p| !p.is_dir) {
+                    let content_type = config.mime_type(&path).unwrap_or("text/html");
+                    let content_type = HeaderValue::from_str(content_type)
+                        .unwrap_or(HeaderValue::from_static("text/html"));
+                    let size = page.size;
+                    let body = if is_head {
+                        Body::Empty
+                    } else {
+                        Body::Full(FileBytesStream::new_with_limited(page.into(), size))
+                    };
+                    return error_page::with_body(resp, content_type, body, size);
+                }
+            }
+            let (content_type, content) = error_page::render(status, format, &instance);
+            let size = content.len() as u64;
+            let body = if is_head {
+                Body::Empty
+            } else {
+                Body::Bytes(content.into())
+            };
+            error_page::with_body(resp, HeaderValue::from_static(content_type), body, size)
+        })
+    }
+
+    /// map the error to the response with the hook, the error is returned without the hook.
+    pub(crate) fn error_response(&self, e: Error) -> Result<Response<Body>> {
+        match self.config.error_hook {
+            Some(ref hook) => Ok((hook.0)(&e)),
+            None => Err(e),
+        }
+    }
+
+    /// the write methods are enabled and the opener is writable.
+    pub(crate) fn is_writable(&self) -> bool {
+        self.config.write && self.opener.is_writable()
+    }
+
+    /// map the error to the response with the hook, the tower fallback has no error of the
+    /// files, the error without the hook is the `500 Internal Server Error`.
+    fn dispatch_error(&self, e: Error, fallthrough: bool) -> Result<Response<Body>> {
+        match self.error_response(e) {
+            Err(_) if fallthrough => Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)?),
+            resp => resp,
+        }
+    }
+
+    /// the tus, the webdav and the write methods take the body of the request, the request is
+    /// never given back to the tower fallback.
+    pub(crate) fn takes_body<B>(&self, request: &Request<B>) -> bool {
+        #[cfg(feature = "tus")]
+        if let Some(ref tus) = self.config.tus {
+            if self.is_writable() && tus.is_endpoint(request.uri().path()) {
+                return true;
+            }
+        }
+        #[cfg(feature = "webdav")]
+        if self.config.webdav && webdav::is_webdav_method(request.method()) {
+            return true;
+        }
+        let method = request.method();
+        let is_form = method == Method::POST && self.config.form_upload;
+        let is_upload = matches!(*method, Method::PUT | Method::PATCH);
+        (is_upload || is_form) && self.is_writable()
+    }
+
+    async fn serv<B>(self, request: Request<B>) -> Result<Response<Body>>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        match self.handle(request, false).await? {
+            Dispatched::Response(resp) => Ok(resp),
+            Dispatched::Fallthrough(_) => unreachable!("the request falls through"),
+        }
+    }
+
+    /// add the CORS headers to the response of the inner service of the tower fallback.
+    #[cfg(feature = "tower")]
+    pub(crate) fn apply_cors(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        #[cfg(feature = "cors")]
+        if let Some(ref cors) = self.config.cors {
+            cors.apply(origin, headers);
+        }
+        #[cfg(not(feature = "cors"))]
+        let _ = (origin, headers);
+    }
+
+    /// serve the request, the request of the missing file or the not allowed method is given
+    /// back if it falls through. both the service and the tower fallback are served by it.
+    pub(crate) async fn handle<B>(
+        self,
+        request: Request<B>,
+        fallthrough: bool,
+    ) -> Result<Dispatched<B>>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        // the `OPTIONS` of the missing path, e.g. the preflight of the api, belongs to the inner
+        // service of the tower fallback.
+        if fallthrough && request.method() == Method::OPTIONS && !self.takes_body(&request) {
+            if let Resolved::NotFound = self
+                .resolve_as_get(&request)
+                .await
+                .map_err(Error::resolve)?
+            {
+                return Ok(Dispatched::Fallthrough(request));
+            }
+        }
+        // answer the preflight and add the CORS headers to the response of the allowed origin.
+        #[cfg(feature = "cors")]
+        let config = self.config.clone();
+        #[cfg(feature = "cors")]
+        if let Some(ref cors) = config.cors {
+            let allow = self.config.allow_header(self.opener.is_writable());
+            if let Some(resp) = cors.preflight(&request, &allow) {
+                return Ok(Dispatched::Response(resp));
+            }
+            // the rejected preflight has no allowed origin.
+            let origin = match cors::is_preflight(&request) {
+                true => None,
+                false => request.headers().get(header::ORIGIN).cloned(),
+            };
+            let mut dispatched = self.dispatch(request, fallthrough).await?;
+            if let Dispatched::Response(ref mut resp) = dispatched {
+                cors.apply(origin.as_ref(), resp.headers_mut());
+            }
+            return Ok(dispatched);
+        }
+        self.dispatch(request, fallthrough).await
+    }
+
+    async fn dispatch<B>(self, request: Request<B>, fallthrough: bool) -> Result<Dispatched<B>>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        let takes_body = self.takes_body(&request);
+        let (parts, body) = request.into_parts();
+        let head = Request::from_parts(parts, ());
+        if takes_body {
+            let resp = match self.write(&head, body).await {
+                Ok(resp) => resp,
+                Err(e) => self.dispatch_error(e, fallthrough)?,
+            };
+            let render = self.render_error(resp, &head);
+            return Ok(Dispatched::Response(render.await));
+        }
+        let resp = match self.read(&head).await {
             Ok(resp) => resp,
-            Err(e) => {
-                let e = Error::new(ErrorKind::Other, e);
-                return Err(e);
+            Err(e) => self.dispatch_error(e, fallthrough)?,
+        };
+        let status = resp.status();
+        if fallthrough
+            && (status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED)
+        {
+            let (parts, ()) = head.into_parts();
+            return Ok(Dispatched::Fallthrough(Request::from_parts(parts, body)));
+        }
+        let render = self.render_error(resp, &head);
+        Ok(Dispatched::Response(render.await))
+    }
+
+    /// the tus, the webdav, the upload and the form, the body is streamed to the files.
+    async fn write<B>(&self, head: &Request<()>, body: B) -> Result<Response<Body>>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxError>,
+    {
+        let (opener, config) = (self.opener.clone(), self.config.clone());
+        #[cfg(feature = "tus")]
+        if let Some(ref tus) = self.config.tus {
+            if self.is_writable() && tus.is_endpoint(head.uri().path()) {
+                return tus::handle(opener, config, head, body).await;
             }
+        }
+        #[cfg(feature = "webdav")]
+        if self.config.webdav && webdav::is_webdav_method(head.method()) {
+            let writable = self.is_writable();
+            return webdav::handle(opener, config, head, body, writable).await;
+        }
+        match *head.method() {
+            Method::POST => form::post(opener, config, head, body).await,
+            _ => write::put_or_patch(opener, config, head, body).await,
+        }
+    }
+
+    /// the delete, the archive and the resolved file, the body is not read.
+    async fn read(&self, head: &Request<()>) -> Result<Response<Body>> {
+        if head.method() == Method::DELETE && self.is_writable() {
+            let (opener, config) = (self.opener.clone(), self.config.clone());
+            return write::delete(opener, config, head, false).await;
+        }
+        #[cfg(feature = "archive")]
+        if self.config.archive && matches!(*head.method(), Method::GET | Method::HEAD) {
+            if let Some(format) = head.uri().query().and_then(archive::Format::from_query) {
+                let archived = archive::respond(&self.opener, &self.config, head, format);
+                if let Some(resp) = archived.await? {
+                    return Ok(resp);
+                }
+            }
+        }
+        let resolved = self.resolve(head).await.map_err(Error::resolve)?;
+        // the headers file of the virtual host is loaded by the request of the host.
+        let host_headers = match resolved {
+            Resolved::Found(_) if self.config.headers_file && self.opener.is_virtual_host() => {
+                let path = host_path(&*self.opener, head, headers::HEADERS_FILE);
+                headers::load_host(&self.opener, &self.config.host_headers, path).await?
+            }
+            _ => Default::default(),
         };
-        Ok(resp)
+        self.respond(resolved, head, &host_headers)
     }
 }
 
-impl<B> Service<Request<B>> for FileService
+impl<O, B> Service<Request<B>> for FileService<O>
 where
-    B: Sync + Send + 'static,
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
 {
     type Response = Response<Body>;
 
@@ -73,20 +470,69 @@ where
     }
 }
 
-#[derive(Clone)]
-pub struct FileServiceMaker {
-    local_root: String,
+pub struct FileServiceMaker<O = LocalFileReaderOpener> {
+    opener: Arc<O>,
+    config: Arc<Config>,
+}
+
+impl<O> Clone for FileServiceMaker<O> {
+    fn clone(&self) -> Self {
+        Self {
+            opener: self.opener.clone(),
+            config: self.config.clone(),
+        }
+    }
 }
 
 impl FileServiceMaker {
     pub fn new(local_root: impl Into<String>) -> Self {
         let local_root = local_root.into();
-        Self { local_root }
+        Self::with_opener(LocalFileReaderOpener::new(local_root))
+    }
+}
+
+impl FileServiceMaker<OverlayFileReaderOpener> {
+    /// create the maker with the ordered roots, the former root shadows the latter.
+    pub fn with_roots<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self::with_opener(OverlayFileReaderOpener::new(roots))
     }
 }
 
-impl<T> Service<T> for FileServiceMaker {
-    type Response = FileService;
+impl FileServiceMaker<MountFileReaderOpener> {
+    /// create the maker with the local roots mounted at the prefixes,
+    /// use the `MountFileReaderOpener` to mount the other backends.
+    pub fn with_mounts<I, P, R>(mounts: I) -> Self
+    where
+        I: IntoIterator<Item = (P, R)>,
+        P: AsRef<str>,
+        R: Into<PathBuf>,
+    {
+        let opener = mounts
+            .into_iter()
+            .fold(MountFileReaderOpener::new(), |opener, (prefix, root)| {
+                opener.mount(prefix, LocalFileReaderOpener::new(root))
+            });
+        Self::with_opener(opener)
+    }
+}
+
+impl<O: FileReaderOpener> FileServiceMaker<O> {
+    /// create the maker, all the services share the opener.
+    pub fn with_opener(opener: O) -> Self {
+        Self::with_config(Arc::new(opener), Arc::default())
+    }
+
+    pub(crate) fn with_config(opener: Arc<O>, config: Arc<Config>) -> Self {
+        Self { opener, config }
+    }
+}
+
+impl<O: FileReaderOpener, T> Service<T> for FileServiceMaker<O> {
+    type Response = FileService<O>;
 
     type Error = hyper::Error;
 
@@ -97,7 +543,79 @@ impl<T> Service<T> for FileServiceMaker {
     }
 
     fn call(&mut self, _: T) -> Self::Future {
-        let local_root = self.local_root.clone();
-        Box::pin(async move { Ok(FileService::new(local_root)) })
+        let svc = FileService::with_config(self.opener.clone(), self.config.clone());
+        Box::pin(async move { Ok(svc) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::Encoding,
+        fixture::{body_string, call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+    use hyper::header::HeaderName;
+
+    #[test]
+    fn test_builder() {
+        let root = TempRoot::new("builder");
+        root.write("site/index.html", "<html></html>")
+            .write("site/app.js", "console.log(1)")
+            .write("site/app.js.br", "br")
+            .write("site/docs/a.txt", "a")
+            .write("site/.git/config", "");
+        let mut svc = FileServiceBuilder::new(root.join("site").to_str().unwrap())
+            .index_file("index.html")
+            .listing(true)
+            .hidden_files(false)
+            .precompressed(Encoding::Brotli)
+            .header(
+                HeaderName::from_static("x-served-by"),
+                HeaderValue::from_static("blockless"),
+            )
+            .build();
+        runtime().block_on(async {
+            let get = |path: &str, accept_encoding: &str| {
+                request("GET", path, &[("accept-encoding", accept_encoding)], "")
+            };
+            let resp = call(&mut svc, get("/", "")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                resp.headers()[header::CONTENT_TYPE],
+                "text/html; charset=utf-8"
+            );
+            assert_eq!(resp.headers()["x-served-by"], "blockless");
+            let resp = call(&mut svc, get("/docs?x=1", "")).await;
+            assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+            assert_eq!(resp.headers()[header::LOCATION], "/docs/?x=1");
+            let resp = call(&mut svc, get("/docs/", "")).await;
+            assert!(body_string(resp).await.contains("./a.txt"));
+            let resp = call(&mut svc, get("/app.js", "gzip, br")).await;
+            assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+            assert_eq!(
+                resp.headers()[header::CONTENT_TYPE],
+                "text/javascript; charset=utf-8"
+            );
+            let resp = call(&mut svc, get("/app.js", "gzip")).await;
+            assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(resp.headers()[header::VARY], "Accept-Encoding");
+            let resp = call(&mut svc, get("/.git/config", "")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+        let mut svc = FileServiceBuilder::new(".").build();
+        runtime().block_on(async {
+            let resp = call(&mut svc, request("POST", "/Cargo.toml", &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(resp.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+            let resp = call(&mut svc, request("OPTIONS", "*", &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(resp.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+        });
     }
 }
@@ -0,0 +1,109 @@
+//! The fixture of the tests, the temporary root is removed when it's dropped, so the failed test
+//! don't leave the files behind.
+use std::{
+    fmt::Debug,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use hyper::{service::Service, Method, Request, Response};
+
+use crate::body::Body;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// the temporary root directory of the test.
+pub(crate) struct TempRoot(PathBuf);
+
+impl TempRoot {
+    pub(crate) fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = format!("{name}-test-{}-{id}", std::process::id());
+        let path = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// the root of the service.
+    pub(crate) fn root(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    pub(crate) fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    pub(crate) fn mkdir(&self, path: &str) -> &Self {
+        fs::create_dir_all(self.join(path)).unwrap();
+        self
+    }
+
+    /// write the file, the parent directories are created.
+    pub(crate) fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> &Self {
+        let path = self.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+        self
+    }
+
+    pub(crate) fn read(&self, path: &str) -> Vec<u8> {
+        fs::read(self.join(path)).unwrap()
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// the current thread runtime drive the test.
+pub(crate) fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+}
+
+/// the request with the method, the headers and the body.
+pub(crate) fn request(
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: impl Into<hyper::Body>,
+) -> Request<hyper::Body> {
+    let mut req = Request::builder()
+        .method(Method::from_bytes(method.as_bytes()).unwrap())
+        .uri(uri);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    req.body(body.into()).unwrap()
+}
+
+/// call the service with the request, the future don't borrow the service.
+pub(crate) fn call<S>(
+    svc: &mut S,
+    req: Request<hyper::Body>,
+) -> impl Future<Output = Response<Body>>
+where
+    S: Service<Request<hyper::Body>, Response = Response<Body>>,
+    S::Error: Debug,
+{
+    let future = svc.call(req);
+    async move { future.await.unwrap() }
+}
+
+/// read the whole body of the response.
+pub(crate) async fn body_string(resp: Response<Body>) -> String {
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
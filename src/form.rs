@@ -0,0 +1,651 @@
+//! The upload of the `multipart/form-data` form, the parts are streamed to the temporary files
+//! and renamed into the directory of the request when the whole form is received.
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hyper::{
+    body::{Buf, HttpBody},
+    header, Request, Response, StatusCode,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+
+use crate::{
+    body::Body,
+    config::Config,
+    error::{BoxError, Error},
+    error_page::escape_json,
+    executor::{BlockingFuture, SharedExecutor},
+    file::FileReaderOpener,
+    listing::HREF_ENCODE_SET,
+    request_resolve::request_path,
+    write::{create_temp, status, target_meta},
+};
+
+const MAX_HEADERS_SIZE: usize = 8 * 1024;
+const MAX_FILENAME_LEN: usize = 255;
+const WRITE_BUF_SIZE: usize = 64 * 1024;
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid multipart body")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// the event of the multipart body.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Event {
+    Part {
+        name: String,
+        filename: Option<String>,
+    },
+    Data(Vec<u8>),
+    PartEnd,
+}
+
+enum State {
+    Preamble,
+    Delimiter,
+    Headers,
+    Body,
+    End,
+}
+
+/// The streaming parser of the multipart body, only the bytes may be the delimiter are kept.
+pub(crate) struct MultipartParser {
+    // the `\r\n--boundary`.
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    state: State,
+}
+
+impl MultipartParser {
+    pub(crate) fn new(boundary: &str) -> Self {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+        Self {
+            delimiter,
+            // the first delimiter has no leading line break.
+            buf: b"\r\n".to_vec(),
+            state: State::Preamble,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        if !matches!(self.state, State::End) {
+            self.buf.extend_from_slice(data);
+        }
+    }
+
+    /// the close delimiter is received.
+    pub(crate) fn is_end(&self) -> bool {
+        matches!(self.state, State::End)
+    }
+
+    /// the next event, none if more data is needed.
+    pub(crate) fn next_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            match self.state {
+                State::Preamble => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        self.buf.drain(..i + self.delimiter.len());
+                        self.state = State::Delimiter;
+                    }
+                    None => {
+                        let keep = self.delimiter.len() - 1;
+                        let n = self.buf.len().saturating_sub(keep);
+                        self.buf.drain(..n);
+                        return Ok(None);
+                    }
+                },
+                State::Delimiter => {
+                    if self.buf.starts_with(b"--") {
+                        self.buf.clear();
+                        self.state = State::End;
+                        return Ok(None);
+                    }
+                    let i = match find(&self.buf, b"\r\n") {
+                        Some(i) => i,
+                        None if self.buf.len() > MAX_HEADERS_SIZE => return Err(invalid()),
+                        None => return Ok(None),
+                    };
+                    // the transport padding after the delimiter.
+                    if !self.buf[..i].iter().all(|b| *b == b' ' || *b == b'\t') {
+                        return Err(invalid());
+                    }
+                    self.buf.drain(..i + 2);
+                    self.state = State::Headers;
+                }
+                State::Headers => {
+                    let i = match find(&self.buf, b"\r\n\r\n") {
+                        Some(i) => i,
+                        None if self.buf.len() > MAX_HEADERS_SIZE => return Err(invalid()),
+                        None => return Ok(None),
+                    };
+                    let headers = String::from_utf8_lossy(&self.buf[..i]).into_owned();
+                    self.buf.drain(..i + 4);
+                    self.state = State::Body;
+                    let disposition = headers.split("\r\n").find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.trim()
+                            .eq_ignore_ascii_case("content-disposition")
+                            .then_some(value)
+                    });
+                    let (name, filename) = disposition
+                        .and_then(parse_disposition)
+                        .ok_or_else(invalid)?;
+                    return Ok(Some(Event::Part { name, filename }));
+                }
+                State::Body => {
+                    return match find(&self.buf, &self.delimiter) {
+                        Some(0) => {
+                            self.buf.drain(..self.delimiter.len());
+                            self.state = State::Delimiter;
+                            Ok(Some(Event::PartEnd))
+                        }
+                        Some(i) => Ok(Some(Event::Data(self.buf.drain(..i).collect()))),
+                        None => {
+                            let keep = self.delimiter.len() - 1;
+                            let n = self.buf.len().saturating_sub(keep);
+                            if n == 0 {
+                                return Ok(None);
+                            }
+                            Ok(Some(Event::Data(self.buf.drain(..n).collect())))
+                        }
+                    };
+                }
+                State::End => return Ok(None),
+            }
+        }
+    }
+}
+
+/// split the parameters of the header, the quoted string may contain the `;`.
+fn split_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = value.chars().peekable();
+    while chars.peek().is_some() {
+        let mut name = String::new();
+        let mut has_value = false;
+        for c in chars.by_ref() {
+            match c {
+                '=' => {
+                    has_value = true;
+                    break;
+                }
+                ';' => break,
+                c => name.push(c),
+            }
+        }
+        let mut value = String::new();
+        if has_value {
+            while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                chars.next();
+            }
+            let quoted = chars.peek() == Some(&'"');
+            if quoted {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c),
+                    }
+                }
+            }
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+                if !quoted {
+                    value.push(c);
+                }
+            }
+        }
+        let name = name.trim().to_ascii_lowercase();
+        if !name.is_empty() {
+            params.push((name, value.trim().to_string()));
+        }
+    }
+    params
+}
+
+/// the name and the filename of the `Content-Disposition: form-data`, the `filename*` of the
+/// RFC 5987 is preferred.
+fn parse_disposition(value: &str) -> Option<(String, Option<String>)> {
+    let mut parts = value.splitn(2, ';');
+    if !parts.next()?.trim().eq_ignore_ascii_case("form-data") {
+        return None;
+    }
+    let params = split_params(parts.next().unwrap_or(""));
+    let param = |name: &str| params.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+    let name = param("name")?.clone();
+    let extended = param("filename*").and_then(|v| {
+        let (charset, rest) = v.split_once('\'')?;
+        let (_, encoded) = rest.split_once('\'')?;
+        let is_utf8 = charset.eq_ignore_ascii_case("utf-8");
+        let decoded = percent_decode_str(encoded);
+        is_utf8.then(|| decoded.decode_utf8_lossy().into_owned())
+    });
+    let filename = extended.or_else(|| param("filename").cloned());
+    Some((name, filename))
+}
+
+/// the last segment of the client filename without the hidden prefix and the reserved
+/// characters, none if nothing left.
+pub(crate) fn sanitize_filename(filename: &str) -> Option<String> {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or("");
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect();
+    let name = name.trim().trim_start_matches('.').trim();
+    let mut len = name.len().min(MAX_FILENAME_LEN);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    let name = &name[..len];
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// the boundary of the `multipart/form-data`.
+fn boundary<B>(request: &Request<B>) -> Option<String> {
+    let content_type = request.headers().get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let mut parts = content_type.splitn(2, ';');
+    if !parts
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    let params = split_params(parts.next()?);
+    let (_, boundary) = params.into_iter().find(|(n, _)| n == "boundary")?;
+    (1..=70).contains(&boundary.len()).then_some(boundary)
+}
+
+/// link the temporary file to the name without the existing file, e.g. `a-1.txt` for `a.txt`,
+/// the link fails on the existing file so the concurrent uploads never overwrite each other.
+fn claim_target(temp: &Path, dir: &Path, name: &str) -> io::Result<String> {
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    };
+    let mut name = name.to_string();
+    for i in 1.. {
+        match fs::hard_link(temp, dir.join(&name)) {
+            Ok(()) => {
+                fs::remove_file(temp)?;
+                return Ok(name);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        name = format!("{stem}-{i}{ext}");
+    }
+    unreachable!()
+}
+
+/// the file field being written.
+struct PartWriter {
+    field: String,
+    name: String,
+    temp: PathBuf,
+    file: File,
+    buf: Vec<u8>,
+    size: u64,
+}
+
+impl PartWriter {
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+/// the file field written to the temporary file.
+struct Received {
+    field: String,
+    name: String,
+    temp: PathBuf,
+    size: u64,
+}
+
+fn remove_temps(executor: &SharedExecutor, temps: Vec<PathBuf>) -> BlockingFuture<()> {
+    BlockingFuture::spawn(&**executor, move || {
+        for temp in temps {
+            let _ = fs::remove_file(temp);
+        }
+        Ok(())
+    })
+}
+
+enum Outcome {
+    Status(StatusCode),
+    Received(Vec<Received>),
+}
+
+/// receive the file fields to the temporary files.
+async fn receive<B>(
+    executor: &SharedExecutor,
+    config: &Config,
+    dir: &Path,
+    boundary: &str,
+    body: B,
+    temps: &mut Vec<PathBuf>,
+) -> Result<Outcome, Error>
+where
+    B: HttpBody,
+{
+    let mut body = Box::pin(body);
+    let mut parser = MultipartParser::new(boundary);
+    let mut received = Vec::new();
+    let mut current: Option<PartWriter> = None;
+    let mut total = 0u64;
+    // the error of the body is dropped before the await, it's maybe not `Send`.
+    while let Some(chunk) = body.data().await.map(Result::ok) {
+        let mut chunk = match chunk {
+            Some(chunk) => chunk,
+            None => return Ok(Outcome::Status(StatusCode::BAD_REQUEST)),
+        };
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            let n = bytes.len();
+            parser.feed(bytes);
+            chunk.advance(n);
+        }
+        loop {
+            let event = match parser.next_event() {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(_) => return Ok(Outcome::Status(StatusCode::BAD_REQUEST)),
+            };
+            match event {
+                Event::Part { name, filename } => {
+                    let filename = filename.as_deref().and_then(sanitize_filename);
+                    current = match filename {
+                        Some(filename) => {
+                            let target = dir.join(&filename);
+                            let (temp, file) =
+                                BlockingFuture::spawn(&**executor, move || create_temp(&target))
+                                    .await?;
+                            temps.push(temp.clone());
+                            Some(PartWriter {
+                                field: name,
+                                name: filename,
+                                temp,
+                                file,
+                                buf: Vec::with_capacity(WRITE_BUF_SIZE),
+                                size: 0,
+                            })
+                        }
+                        // the field without the file is skipped.
+                        None => None,
+                    };
+                }
+                Event::Data(data) => {
+                    total += data.len() as u64;
+                    if config.max_upload_size.map(|m| total > m).unwrap_or(false) {
+                        return Ok(Outcome::Status(StatusCode::PAYLOAD_TOO_LARGE));
+                    }
+                    let mut writer = match current.take() {
+                        Some(writer) => writer,
+                        None => continue,
+                    };
+                    writer.size += data.len() as u64;
+                    let max_file_size = config.max_form_file_size;
+                    if max_file_size.map(|m| writer.size > m).unwrap_or(false) {
+                        return Ok(Outcome::Status(StatusCode::PAYLOAD_TOO_LARGE));
+                    }
+                    writer.buf.extend_from_slice(&data);
+                    if writer.buf.len() >= WRITE_BUF_SIZE {
+                        writer = BlockingFuture::spawn(&**executor, move || {
+                            writer.flush()?;
+                            Ok(writer)
+                        })
+                        .await?;
+                    }
+                    current = Some(writer);
+                }
+                Event::PartEnd => {
+                    if let Some(mut writer) = current.take() {
+                        let writer = BlockingFuture::spawn(&**executor, move || {
+                            writer.flush()?;
+                            writer.file.sync_all()?;
+                            Ok(writer)
+                        })
+                        .await?;
+                        received.push(Received {
+                            field: writer.field,
+                            name: writer.name,
+                            temp: writer.temp,
+                            size: writer.size,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    if !parser.is_end() {
+        return Ok(Outcome::Status(StatusCode::BAD_REQUEST));
+    }
+    Ok(Outcome::Received(received))
+}
+
+/// upload the file fields of the form into the directory of the request, the files are
+/// renamed only when the whole form is received, the existing file is never replaced.
+pub(crate) async fn post<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let boundary = match boundary(head) {
+        Some(boundary) => boundary,
+        None => return status(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+    };
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    let executor = opener.blocking_executor();
+    let create_dirs = config.create_dirs;
+    let dir = BlockingFuture::spawn(&*executor, move || {
+        let dir = match opener.local_path(&path) {
+            Some(dir) => dir,
+            None => return Ok(Err(StatusCode::METHOD_NOT_ALLOWED)),
+        };
+        match target_meta(&dir)? {
+            Some(meta) if meta.is_dir() => Ok(Ok(dir)),
+            Some(_) => Ok(Err(StatusCode::CONFLICT)),
+            None if create_dirs => fs::create_dir_all(&dir).map(|_| Ok(dir)),
+            None => Ok(Err(StatusCode::NOT_FOUND)),
+        }
+    })
+    .await?;
+    let dir = match dir {
+        Ok(dir) => dir,
+        Err(s) => return status(s),
+    };
+    let mut temps = Vec::new();
+    let received = receive(&executor, &config, &dir, &boundary, body, &mut temps).await;
+    let received = match received {
+        Ok(Outcome::Received(received)) => received,
+        Ok(Outcome::Status(s)) => {
+            remove_temps(&executor, temps).await?;
+            return status(s);
+        }
+        Err(e) => {
+            remove_temps(&executor, temps).await?;
+            return Err(e);
+        }
+    };
+    let renamed = BlockingFuture::spawn(&*executor, move || {
+        let mut renamed = Vec::with_capacity(received.len());
+        for received in received {
+            let name = claim_target(&received.temp, &dir, &received.name)?;
+            renamed.push(Received { name, ..received });
+        }
+        Ok(renamed)
+    })
+    .await;
+    let renamed = match renamed {
+        Ok(renamed) => renamed,
+        Err(e) => {
+            remove_temps(&executor, temps).await?;
+            return Err(e.into());
+        }
+    };
+    let uri_path = head.uri().path().trim_end_matches('/');
+    let files = renamed
+        .iter()
+        .map(|r| {
+            // the path of the request is encoded already.
+            let path = format!(
+                "{uri_path}/{}",
+                utf8_percent_encode(&r.name, HREF_ENCODE_SET)
+            );
+            format!(
+                "{{\"field\":\"{}\",\"name\":\"{}\",\"path\":\"{}\",\"size\":{}}}",
+                escape_json(&r.field),
+                escape_json(&r.name),
+                escape_json(&path),
+                r.size
+            )
+        })
+        .collect::<Vec<_>>();
+    let summary = format!("{{\"files\":[{}]}}", files.join(","));
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_LENGTH, summary.len())
+        .body(Body::Bytes(summary.into()))?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{body_string, call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_multipart_parser() {
+        let body = "preamble\r\n--xyz\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+             hello\r\n--xyz\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"../a;b.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             line1\r\n--xy line2\r\n--xyz--\r\n";
+        // feed byte by byte, the delimiter may be split.
+        let mut parser = MultipartParser::new("xyz");
+        let mut events = Vec::new();
+        for b in body.as_bytes() {
+            parser.feed(&[*b]);
+            while let Some(event) = parser.next_event().unwrap() {
+                events.push(event);
+            }
+        }
+        assert!(parser.is_end());
+        let data = |events: &[Event]| {
+            events
+                .iter()
+                .filter_map(|e| match e {
+                    Event::Data(d) => Some(d.clone()),
+                    _ => None,
+                })
+                .flatten()
+                .collect::<Vec<u8>>()
+        };
+        let end = events.iter().position(|e| *e == Event::PartEnd).unwrap();
+        assert_eq!(
+            events[0],
+            Event::Part {
+                name: "title".into(),
+                filename: None
+            }
+        );
+        assert_eq!(data(&events[..end]), b"hello");
+        assert_eq!(
+            events[end + 1],
+            Event::Part {
+                name: "file".into(),
+                filename: Some("../a;b.txt".into())
+            }
+        );
+        assert_eq!(data(&events[end + 1..]), b"line1\r\n--xy line2");
+        assert_eq!(sanitize_filename("../a;b.txt").unwrap(), "a;b.txt");
+        assert_eq!(sanitize_filename("C:\\x\\..\\.env").unwrap(), "env");
+        assert!(sanitize_filename("..").is_none());
+        let (_, filename) =
+            parse_disposition("form-data; name=f; filename*=UTF-8''%E4%BD%A0.txt").unwrap();
+        assert_eq!(filename.unwrap(), "\u{4f60}.txt");
+    }
+
+    #[test]
+    fn test_form_upload() {
+        let root = TempRoot::new("form");
+        root.write("in/a.txt", "old");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .form_upload(true)
+            .max_form_file_size(8)
+            .build();
+        runtime().block_on(async {
+            let mut post = |body: &'static str| {
+                let headers = [("content-type", "multipart/form-data; boundary=\"b\"")];
+                call(&mut svc, request("POST", "/in/", &headers, body))
+            };
+            let resp = post(
+                "--b\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n\
+                 --b\r\nContent-Disposition: form-data; name=\"f\"; filename=\"../a.txt\"\r\n\r\n\
+                 new\r\n--b--\r\n",
+            )
+            .await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert_eq!(
+                body_string(resp).await,
+                r#"{"files":[{"field":"f","name":"a-1.txt","path":"/in/a-1.txt","size":3}]}"#
+            );
+            let resp = post(
+                "--b\r\nContent-Disposition: form-data; name=\"f\"; filename=\"b.txt\"\r\n\r\n\
+                 too large file\r\n--b--\r\n",
+            )
+            .await;
+            assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            let resp =
+                post("--b\r\nContent-Disposition: form-data; name=\"f\"; filename=\"c\"").await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        });
+        assert_eq!(root.read("in/a.txt"), b"old");
+        assert_eq!(root.read("in/a-1.txt"), b"new");
+        assert_eq!(fs::read_dir(root.join("in")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_claim_target() {
+        let root = TempRoot::new("form-claim");
+        root.write("a.txt", "a")
+            .write("a-1.txt", "a1")
+            .write(".temp", "new");
+        let name = claim_target(&root.join(".temp"), root.path(), "a.txt").unwrap();
+        assert_eq!(name, "a-2.txt");
+        assert_eq!(root.read("a.txt"), b"a");
+        assert_eq!(root.read("a-1.txt"), b"a1");
+        assert_eq!(root.read("a-2.txt"), b"new");
+        assert!(!root.join(".temp").exists());
+    }
+}
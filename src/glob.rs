@@ -0,0 +1,64 @@
+/// match the path with the glob pattern, the `*` matches any characters except the `/`,
+/// the `**` matches any characters, the `?` matches one character except the `/`.
+/// the pattern without the `/` matches the last segment of the path, e.g. `*.html`.
+pub(crate) fn glob_match(pattern: &str, path: &str) -> bool {
+    let path = path.trim_start_matches('/');
+    if pattern.contains('/') {
+        let pattern = pattern.trim_start_matches('/');
+        match_bytes(pattern.as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or("");
+        match_bytes(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+fn match_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // the `**/` also matches no directory.
+            let rest_no_slash = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=path.len()).any(|i| {
+                match_bytes(rest, &path[i..])
+                    || (rest_no_slash.len() != rest.len() && match_bytes(rest_no_slash, &path[i..]))
+            })
+        }
+        [b'*', rest @ ..] => {
+            for i in 0..=path.len() {
+                if match_bytes(rest, &path[i..]) {
+                    return true;
+                }
+                if path.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => match_bytes(rest, path),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, path @ ..] if c == p => match_bytes(rest, path),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.html", "/a/b/index.html"));
+        assert!(!glob_match("*.html", "/a/b/index.htm"));
+        assert!(glob_match("/assets/*", "/assets/app.js"));
+        assert!(!glob_match("/assets/*", "/assets/js/app.js"));
+        assert!(glob_match("/assets/**", "/assets/js/app.js"));
+        assert!(glob_match("/app/**/*.js", "/app/app.js"));
+        assert!(glob_match("/app/**/*.js", "/app/a/b/app.js"));
+        assert!(glob_match("/a?c", "/abc"));
+        assert!(!glob_match("/a?c", "/a/c"));
+    }
+}
@@ -0,0 +1,178 @@
+//! The custom headers of the paths, configured by the builder or by the `_headers` file in
+//! the root, e.g.
+//!
+//! ```text
+//! # the comment line.
+//! /assets/**
+//!   Cache-Control: public, max-age=31536000, immutable
+//! /*.html
+//!   Content-Security-Policy: default-src 'self'
+//!   Link: </app.js>; rel=preload; as=script
+//! ```
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    HeaderMap,
+};
+
+use crate::{executor::BlockingFuture, file::FileReaderOpener, glob::glob_match};
+
+/// the name of the headers file in the root.
+pub(crate) const HEADERS_FILE: &str = "_headers";
+
+/// the headers of the paths match the glob pattern.
+#[derive(Debug, Clone)]
+pub(crate) struct PathHeaders {
+    pub(crate) pattern: String,
+    pub(crate) headers: HeaderMap,
+}
+
+/// parse the headers file, the path line starts without the space and the header lines
+/// under it are indented. the invalid line is skipped.
+pub(crate) fn parse(text: &str) -> Vec<PathHeaders> {
+    let mut rules: Vec<PathHeaders> = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with([' ', '\t']) {
+            rules.push(PathHeaders {
+                pattern: trimmed.to_string(),
+                headers: HeaderMap::new(),
+            });
+            continue;
+        }
+        let (rule, (name, value)) = match (rules.last_mut(), trimmed.split_once(':')) {
+            (Some(rule), Some(header)) => (rule, header),
+            _ => continue,
+        };
+        let name = HeaderName::from_bytes(name.trim().as_bytes());
+        let value = HeaderValue::from_str(value.trim());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            rule.headers.append(name, value);
+        }
+    }
+    rules
+}
+
+/// load the headers file in the root of the opener with the local files, the opener without
+/// the local files or the missing file has no rules. the virtual hosts load their files by the
+/// requests.
+pub(crate) fn load<O: FileReaderOpener>(opener: &O) -> Vec<PathHeaders> {
+    opener
+        .local_path(Path::new(HEADERS_FILE))
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| parse(&text))
+        .unwrap_or_default()
+}
+
+/// the rules of the headers files of the virtual hosts by the local path of the file, the
+/// unknown hosts share the file of the default host.
+#[derive(Debug, Default)]
+pub(crate) struct HostHeaders(Mutex<HashMap<PathBuf, Arc<Vec<PathHeaders>>>>);
+
+/// load the headers file of the host in the blocking thread, the `path` is the headers file
+/// under the host, e.g. `example.com/_headers`. the file is read once by the first request.
+pub(crate) fn load_host<O: FileReaderOpener>(
+    opener: &Arc<O>,
+    cache: &Arc<HostHeaders>,
+    path: PathBuf,
+) -> BlockingFuture<Arc<Vec<PathHeaders>>> {
+    let (opener, cache) = (opener.clone(), cache.clone());
+    let executor = opener.blocking_executor();
+    BlockingFuture::spawn(&*executor, move || {
+        let file = match opener.local_path(&path) {
+            Some(file) => file,
+            None => return Ok(Arc::default()),
+        };
+        if let Some(rules) = cache.0.lock().unwrap().get(&file) {
+            return Ok(rules.clone());
+        }
+        let text = fs::read_to_string(&file).unwrap_or_default();
+        let rules = Arc::new(parse(&text));
+        cache.0.lock().unwrap().insert(file, rules.clone());
+        Ok(rules)
+    })
+}
+
+/// add the headers of the matched rules which not in the response, the former rule wins.
+pub(crate) fn apply(rules: &[PathHeaders], path: &str, headers: &mut HeaderMap) {
+    for rule in rules.iter().filter(|r| glob_match(&r.pattern, path)) {
+        for name in rule.headers.keys() {
+            if headers.contains_key(name) {
+                continue;
+            }
+            for value in rule.headers.get_all(name) {
+                headers.append(name, value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+    use hyper::header;
+
+    #[test]
+    fn test_parse() {
+        let rules = parse(
+            "# comment\n/assets/**\n  X-A: 1\n  bad line\n\n/*.html\n\tLink: </a.js>\n\tLink: </b.js>\n  X-A: 2\n",
+        );
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].pattern, "/assets/**");
+        assert_eq!(rules[0].headers.len(), 1);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-a", HeaderValue::from_static("0"));
+        apply(&rules, "/index.html", &mut headers);
+        assert_eq!(headers["x-a"], "0");
+        assert_eq!(headers.get_all("link").iter().count(), 2);
+    }
+
+    #[test]
+    fn test_path_headers() {
+        let root = TempRoot::new("headers");
+        root.write("index.html", "<html></html>")
+            .write("a.txt", "a")
+            .write(
+                HEADERS_FILE,
+                "/*.html\n  Content-Security-Policy: default-src 'self'\n  Content-Length: 1\n",
+            );
+        let mut svc = FileServiceBuilder::new(root.root())
+            .path_header(
+                "*.html",
+                HeaderName::from_static("link"),
+                HeaderValue::from_static("</app.js>; rel=preload; as=script"),
+            )
+            .path_header(
+                "*.html",
+                HeaderName::from_static("link"),
+                HeaderValue::from_static("</app.css>; rel=preload; as=style"),
+            )
+            .headers_file(true)
+            .build();
+        runtime().block_on(async {
+            let resp = call(&mut svc, request("GET", "/index.html", &[], "")).await;
+            let headers = resp.headers();
+            assert_eq!(headers.get_all(header::LINK).iter().count(), 2);
+            assert_eq!(
+                headers[header::CONTENT_SECURITY_POLICY],
+                "default-src 'self'"
+            );
+            assert_eq!(headers[header::CONTENT_LENGTH], "13");
+            let resp = call(&mut svc, request("GET", "/a.txt", &[], "")).await;
+            assert!(!resp.headers().contains_key(header::LINK));
+        });
+    }
+}
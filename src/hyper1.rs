@@ -0,0 +1,212 @@
+//! The hyper 1.x support, the request and response are converted between the http 1.x
+//! and the http 0.2 used by the file service.
+use std::{
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http_body1::{Body as HttpBody1, Frame, SizeHint};
+use hyper::body::{Bytes, HttpBody};
+
+use crate::{body::Body, error::BoxError, file::FileReaderOpener, filesvr::FileService};
+
+impl HttpBody1 for Body {
+    type Data = Bytes;
+
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Self::Data>, Self::Error>>> {
+        match HttpBody::poll_data(self, cx) {
+            Poll::Ready(Some(Ok(data))) => Poll::Ready(Some(Ok(Frame::data(data)))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        HttpBody::is_end_stream(self)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let hint = HttpBody::size_hint(self);
+        let mut size_hint = SizeHint::new();
+        size_hint.set_lower(hint.lower());
+        if let Some(upper) = hint.upper() {
+            size_hint.set_upper(upper);
+        }
+        size_hint
+    }
+}
+
+/// The http 1.x request body used as the http 0.2 body, the trailers are ignored.
+pub(crate) struct Http1Body<B>(Pin<Box<B>>);
+
+impl<B: HttpBody1> HttpBody for Http1Body<B> {
+    type Data = B::Data;
+
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Self::Data, Self::Error>>> {
+        loop {
+            match self.0.as_mut().poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                    Ok(data) => return Poll::Ready(Some(Ok(data))),
+                    Err(_) => continue,
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<std::result::Result<Option<hyper::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_end_stream()
+    }
+}
+
+fn invalid<E>(_: E) -> Error {
+    Error::new(ErrorKind::InvalidInput, "invalid http message")
+}
+
+fn version_from_http1(version: http1::Version) -> hyper::Version {
+    match version {
+        http1::Version::HTTP_09 => hyper::Version::HTTP_09,
+        http1::Version::HTTP_10 => hyper::Version::HTTP_10,
+        http1::Version::HTTP_2 => hyper::Version::HTTP_2,
+        http1::Version::HTTP_3 => hyper::Version::HTTP_3,
+        _ => hyper::Version::HTTP_11,
+    }
+}
+
+fn version_to_http1(version: hyper::Version) -> http1::Version {
+    match version {
+        hyper::Version::HTTP_09 => http1::Version::HTTP_09,
+        hyper::Version::HTTP_10 => http1::Version::HTTP_10,
+        hyper::Version::HTTP_2 => http1::Version::HTTP_2,
+        hyper::Version::HTTP_3 => http1::Version::HTTP_3,
+        _ => http1::Version::HTTP_11,
+    }
+}
+
+/// convert the head of the http 1.x request to the http 0.2 request without the body.
+pub(crate) fn request_head_from_http1<B>(req: &http1::Request<B>) -> Result<hyper::Request<()>> {
+    let method = hyper::Method::from_bytes(req.method().as_str().as_bytes()).map_err(invalid)?;
+    let mut builder = hyper::Request::builder()
+        .method(method)
+        .uri(req.uri().to_string())
+        .version(version_from_http1(req.version()));
+    for (name, value) in req.headers().iter() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    builder.body(()).map_err(invalid)
+}
+
+/// convert the http 1.x request to the http 0.2 request, the body is wrapped.
+pub(crate) fn request_from_http1<B>(
+    req: http1::Request<B>,
+) -> Result<hyper::Request<Http1Body<B>>> {
+    let head = request_head_from_http1(&req)?;
+    Ok(head.map(|_| Http1Body(Box::pin(req.into_body()))))
+}
+
+/// convert the http 0.2 response to the http 1.x response, the body is kept.
+pub(crate) fn response_to_http1<B>(resp: hyper::Response<B>) -> Result<http1::Response<B>> {
+    let (parts, body) = resp.into_parts();
+    let mut builder = http1::Response::builder()
+        .status(parts.status.as_u16())
+        .version(version_to_http1(parts.version));
+    for (name, value) in parts.headers.iter() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    builder.body(body).map_err(invalid)
+}
+
+/// merge the http 0.2 headers into the http 1.x headers, the `Vary` is appended and the other
+/// headers are replaced.
+#[cfg(feature = "tower")]
+pub(crate) fn merge_headers_to_http1(from: &hyper::HeaderMap, to: &mut http1::HeaderMap) {
+    for (name, value) in from.iter() {
+        let name = http1::HeaderName::from_bytes(name.as_str().as_bytes());
+        let value = http1::HeaderValue::from_bytes(value.as_bytes());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            if name == http1::header::VARY {
+                to.append(name, value);
+            } else {
+                to.insert(name, value);
+            }
+        }
+    }
+}
+
+impl<O, B> hyper1::service::Service<http1::Request<B>> for FileService<O>
+where
+    O: FileReaderOpener,
+    B: HttpBody1 + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Response = http1::Response<Body>;
+
+    type Error = crate::Error;
+
+    type Future =
+        Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>> + Send>>;
+
+    fn call(&self, request: http1::Request<B>) -> Self::Future {
+        let mut svc = self.clone();
+        Box::pin(async move {
+            let resp = match request_from_http1(request) {
+                Ok(request) => hyper::service::Service::call(&mut svc, request).await?,
+                Err(e) => svc.error_response(crate::Error::resolve(e))?,
+            };
+            Ok(response_to_http1(resp)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::future::poll_fn;
+    use hyper1::service::Service;
+
+    #[test]
+    fn test_hyper1_service() {
+        let svc = FileService::new(".");
+        let req = http1::Request::get("/Cargo.toml")
+            .header(http1::header::RANGE, "bytes=1-7")
+            .body(String::new())
+            .unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let resp = svc.call(req).await.unwrap();
+            assert_eq!(resp.status(), http1::StatusCode::PARTIAL_CONTENT);
+            assert_eq!(resp.headers()[http1::header::CONTENT_LENGTH], "7");
+            let mut body = resp.into_body();
+            let mut data = Vec::new();
+            while let Some(frame) = poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+                data.extend_from_slice(frame.unwrap().data_ref().unwrap());
+            }
+            assert_eq!(&data[..], b"package");
+        });
+    }
+}
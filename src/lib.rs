@@ -1,9 +1,66 @@
+#[cfg(feature = "archive")]
+mod archive;
 mod body;
+#[cfg(feature = "cas")]
+mod cas;
+mod config;
+#[cfg(feature = "cors")]
+mod cors;
+mod disposition;
 mod error;
+mod error_page;
+mod executor;
+#[cfg(feature = "tower")]
+mod fallback;
 mod file;
 mod filesvr;
+#[cfg(test)]
+mod fixture;
+mod form;
+mod glob;
+mod headers;
+#[cfg(feature = "hyper1")]
+mod hyper1;
+mod listing;
+mod mount;
+mod overlay;
 mod range;
 mod request_resolve;
 mod resp_builder;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "tus")]
+mod tus;
+mod vhost;
+#[cfg(feature = "webdav")]
+mod webdav;
+mod write;
 
-pub use filesvr::FileServiceMaker;
+pub use body::Body;
+#[cfg(feature = "cas")]
+pub use cas::{CasFileReaderOpener, HashAlgorithm};
+pub use config::{Encoding, FileServiceBuilder};
+pub use error::{BoxError, Error, ParseError};
+pub use error_page::ErrorContext;
+#[cfg(feature = "tokio")]
+pub use executor::TokioExecutor;
+pub use executor::{
+    default_executor, BlockingExecutor, BlockingFuture, BlockingTask, SharedExecutor,
+    ThreadExecutor,
+};
+#[cfg(feature = "tower")]
+pub use fallback::{Fallback, FallbackBody, FileServiceLayer, NotFound};
+#[cfg(feature = "tokio")]
+pub use file::TokioFileReaderOpener;
+pub use file::{
+    BlockingFileReader, BoxFileReader, DirEntry, FileReader, FileReaderOpener, FileWithMeta,
+    FileWithMetaFuture, LocalFileReaderOpener, ReadDirFuture,
+};
+pub use filesvr::{FileService, FileServiceMaker};
+pub use mount::{BoxOpenFuture, MountFileReaderOpener};
+pub use overlay::{OverlayFileReaderOpener, OPAQUE_WHITEOUT, WHITEOUT_PREFIX};
+#[cfg(feature = "s3")]
+pub use s3::{S3FileReaderOpener, S3ObjectReader, S3OpenFuture};
+pub use vhost::VirtualHostFileReaderOpener;
+#[cfg(feature = "webdav")]
+pub use webdav::{Lock, LockStore};
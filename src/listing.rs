@@ -0,0 +1,97 @@
+use std::fmt::Write;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::file::DirEntry;
+
+// the characters escaped in the href of the entry.
+pub(crate) const HREF_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'\'');
+
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// render the html listing of the directory, the directories are listed before the files.
+pub(crate) fn render(uri_path: &str, mut entries: Vec<DirEntry>) -> String {
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    let title = escape_html(uri_path);
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n\
+         <body>\n<h1>Index of {title}</h1>\n<table>\n"
+    );
+    if uri_path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let slash = if entry.is_dir { "/" } else { "" };
+        let href = utf8_percent_encode(&entry.name, HREF_ENCODE_SET);
+        let name = escape_html(&entry.name);
+        let size = if entry.is_dir {
+            "-".to_string()
+        } else {
+            entry.size.to_string()
+        };
+        let modified = entry
+            .modified
+            .map(httpdate::fmt_http_date)
+            .unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr><td><a href=\"./{href}{slash}\">{name}{slash}</a></td><td>{size}</td><td>{modified}</td></tr>"
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let entry = |name: &str, is_dir| DirEntry {
+            name: name.to_string(),
+            is_dir,
+            size: 3,
+            modified: None,
+        };
+        let html = render(
+            "/a<b>/",
+            vec![
+                entry("z.txt", false),
+                entry("a b#\"<x>", false),
+                entry("dir", true),
+            ],
+        );
+        assert!(html.contains("<title>Index of /a&lt;b&gt;/</title>"));
+        assert!(html.contains("<a href=\"./a%20b%23%22%3Cx%3E\">a b#&quot;&lt;x&gt;</a>"));
+        let dir = html.find("./dir/").unwrap();
+        let file = html.find("./z.txt").unwrap();
+        assert!(dir < file);
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use crate::{
+    file::{FileReaderOpener, FileWithMeta, ReadDirFuture},
+    request_resolve::sanitize_path,
+};
+
+/// The boxed future of the mounted opener.
+pub type BoxOpenFuture = Pin<Box<dyn Future<Output = Result<FileWithMeta>> + Send + 'static>>;
+
+/// the object safe opener, every opener is boxed as it.
+trait DynOpener: Send + Sync + 'static {
+    fn open_dyn(&self, path: &Path) -> BoxOpenFuture;
+
+    fn read_dir_dyn(&self, path: &Path) -> ReadDirFuture;
+
+    fn is_writable_dyn(&self) -> bool;
+
+    fn local_path_dyn(&self, path: &Path) -> Option<PathBuf>;
+}
+
+impl<O: FileReaderOpener> DynOpener for O {
+    fn open_dyn(&self, path: &Path) -> BoxOpenFuture {
+        let fut = self.open(path);
+        Box::pin(async move { fut.await.map(Into::into) })
+    }
+
+    fn read_dir_dyn(&self, path: &Path) -> ReadDirFuture {
+        self.read_dir(path)
+    }
+
+    fn is_writable_dyn(&self) -> bool {
+        self.is_writable()
+    }
+
+    fn local_path_dyn(&self, path: &Path) -> Option<PathBuf> {
+        self.local_path(path)
+    }
+}
+
+struct Mount {
+    // the sanitized prefix, empty mean the mount of the root.
+    prefix: PathBuf,
+    opener: Box<dyn DynOpener>,
+    // the opener select the root by the host, the host is kept before the path.
+    virtual_host: bool,
+}
+
+/// The opener dispatches the path to the opener mounted at the longest matched prefix,
+/// the prefix is stripped before the path is opened. The path outside all the mounts is not found.
+/// The virtual host opener can be mounted, it gets the host of the request before the path.
+///
+/// ```ignore
+/// let opener = MountFileReaderOpener::new()
+///     .mount("/static", LocalFileReaderOpener::new("./assets"))
+///     .mount("/media", OverlayFileReaderOpener::new(["./upload", "./media"]));
+/// ```
+#[derive(Default)]
+pub struct MountFileReaderOpener {
+    mounts: Vec<Mount>,
+}
+
+impl MountFileReaderOpener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// mount the opener at the prefix, the prefix is matched by the whole path segments,
+    /// e.g. `/static` matches `/static/a.js` but not `/statics/a.js`.
+    pub fn mount<O: FileReaderOpener>(mut self, prefix: impl AsRef<str>, opener: O) -> Self {
+        let prefix = sanitize_path(prefix.as_ref());
+        self.mounts.retain(|m| m.prefix != prefix);
+        self.mounts.push(Mount {
+            prefix,
+            virtual_host: opener.is_virtual_host(),
+            opener: Box::new(opener),
+        });
+        // the longer prefix is matched first.
+        self.mounts.sort_by(|a, b| {
+            let a = a.prefix.components().count();
+            let b = b.prefix.components().count();
+            b.cmp(&a)
+        });
+        self
+    }
+
+    /// find the mount and the path relative to its root, the path begin with the host if any
+    /// mount is the virtual host, the host is kept for the virtual host mount.
+    fn route(&self, path: &Path) -> Option<(&Mount, PathBuf)> {
+        let (host, path) = match self.is_virtual_host() {
+            true => {
+                let mut comps = path.iter();
+                (comps.next(), comps.as_path())
+            }
+            false => (None, path),
+        };
+        self.mounts.iter().find_map(|m| {
+            let rest = path.strip_prefix(&m.prefix).ok()?;
+            match host {
+                Some(host) if m.virtual_host => Some((m, Path::new(host).join(rest))),
+                _ => Some((m, rest.to_path_buf())),
+            }
+        })
+    }
+}
+
+fn not_mounted() -> Error {
+    Error::new(ErrorKind::NotFound, "the path is not mounted")
+}
+
+impl FileReaderOpener for MountFileReaderOpener {
+    type Output = FileWithMeta;
+
+    type Future = BoxOpenFuture;
+
+    fn open<T: AsRef<Path>>(&self, path: T) -> Self::Future {
+        match self.route(path.as_ref()) {
+            Some((m, rest)) => m.opener.open_dyn(&rest),
+            None => Box::pin(async { Err(not_mounted()) }),
+        }
+    }
+
+    fn read_dir<T: AsRef<Path>>(&self, path: T) -> ReadDirFuture {
+        match self.route(path.as_ref()) {
+            Some((m, rest)) => m.opener.read_dir_dyn(&rest),
+            None => ReadDirFuture::ready(Err(not_mounted())),
+        }
+    }
+
+    fn is_virtual_host(&self) -> bool {
+        self.mounts.iter().any(|m| m.virtual_host)
+    }
+
+    /// the write methods are enabled if any mount is writable, the path of the read only mount
+    /// has no local path, it's `405 Method Not Allowed`.
+    fn is_writable(&self) -> bool {
+        self.mounts.iter().any(|m| m.opener.is_writable_dyn())
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        let (m, rest) = self.route(path)?;
+        if !m.opener.is_writable_dyn() {
+            return None;
+        }
+        m.opener.local_path_dyn(&rest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LocalFileReaderOpener, OverlayFileReaderOpener, VirtualHostFileReaderOpener};
+
+    #[test]
+    fn test_route() {
+        let opener = MountFileReaderOpener::new()
+            .mount("/static/", LocalFileReaderOpener::new("./src"))
+            .mount("/static/js", LocalFileReaderOpener::new("./examples"))
+            .mount("/", LocalFileReaderOpener::new("."));
+        let rest = |p: &str| {
+            opener
+                .route(Path::new(p))
+                .map(|(_, rest)| rest.to_str().unwrap().to_string())
+        };
+        assert_eq!(rest("static/lib.rs").unwrap(), "lib.rs");
+        assert_eq!(rest("static/js/a.js").unwrap(), "a.js");
+        assert_eq!(rest("statics/a.js").unwrap(), "statics/a.js");
+
+        let opener = MountFileReaderOpener::new().mount("/static", LocalFileReaderOpener::new("."));
+        let rest = |p: &str| {
+            opener
+                .route(Path::new(p))
+                .map(|(_, rest)| rest.to_path_buf())
+        };
+        assert!(rest("Cargo.toml").is_none());
+        assert_eq!(rest("static/Cargo.toml").unwrap(), Path::new("Cargo.toml"));
+    }
+
+    #[test]
+    fn test_mixed_mounts() {
+        let opener = MountFileReaderOpener::new()
+            .mount("/sites", VirtualHostFileReaderOpener::new("./sites"))
+            .mount("/layers", OverlayFileReaderOpener::new(["./a", "./b"]))
+            .mount("/", LocalFileReaderOpener::new("./public"));
+        assert!(opener.is_virtual_host());
+        let rest = |p: &str| opener.route(Path::new(p)).map(|(_, rest)| rest);
+        assert_eq!(
+            rest("a.com/sites/index.html").unwrap(),
+            Path::new("a.com/index.html")
+        );
+        assert_eq!(rest("a.com/x/a.txt").unwrap(), Path::new("x/a.txt"));
+        let local_path = |p: &str| opener.local_path(Path::new(p));
+        assert_eq!(
+            local_path("a.com/a.txt").unwrap(),
+            Path::new("./public/a.txt")
+        );
+        // the overlay is read only, the write methods are not allowed.
+        assert!(local_path("a.com/layers/a.txt").is_none());
+    }
+}
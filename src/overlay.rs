@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    executor::{default_executor, BlockingExecutor, SharedExecutor},
+    file::{DirEntry, FileReaderOpener, FileWithMeta, FileWithMetaFuture, ReadDirFuture},
+};
+
+/// the marker `.wh.<name>` in the upper layer hides the `<name>` of the lower layers.
+pub const WHITEOUT_PREFIX: &str = ".wh.";
+/// the marker in the upper layer directory hides all the entries of the lower layers.
+pub const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// The opener of the union filesystem, the roots are the layers ordered from the top.
+/// The file in the upper layer shadows the same path in the lower layers, the whiteout
+/// marker file `.wh.<name>` hides the `<name>` of the lower layers.
+pub struct OverlayFileReaderOpener {
+    roots: Arc<Vec<PathBuf>>,
+    executor: SharedExecutor,
+}
+
+/// the path result of the layer.
+enum Lookup {
+    // the path exist in the layer.
+    Found(PathBuf),
+    // the path is hidden by the whiteout in the layer, stop lookup the lower layers.
+    Whiteout,
+    // the path not exist in the layer, continue lookup the lower layers.
+    Missing,
+}
+
+impl OverlayFileReaderOpener {
+    pub fn new<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        let roots = roots.into_iter().map(Into::into).collect();
+        Self {
+            roots: Arc::new(roots),
+            executor: default_executor(),
+        }
+    }
+
+    /// the executor run the blocking file operations, default is the tokio executor.
+    pub fn executor(mut self, executor: impl BlockingExecutor) -> Self {
+        self.executor = Arc::new(executor);
+        self
+    }
+
+    fn is_whiteout_name(name: &str) -> bool {
+        name.starts_with(WHITEOUT_PREFIX)
+    }
+
+    fn lookup(root: &Path, path: &Path) -> Lookup {
+        let full_path = root.join(path);
+        if full_path.symlink_metadata().is_ok() {
+            return Lookup::Found(full_path);
+        }
+        // check the whiteout of the path and all the parents in the layer.
+        let mut parent = root.to_path_buf();
+        for comp in path.iter() {
+            let mut whiteout = std::ffi::OsString::from(WHITEOUT_PREFIX);
+            whiteout.push(comp);
+            if parent.join(&whiteout).symlink_metadata().is_ok() {
+                return Lookup::Whiteout;
+            }
+            parent.push(comp);
+            if parent.join(OPAQUE_WHITEOUT).symlink_metadata().is_ok() && parent != full_path {
+                return Lookup::Whiteout;
+            }
+        }
+        Lookup::Missing
+    }
+
+    fn open_blocking(
+        roots: &[PathBuf],
+        path: &Path,
+        executor: &SharedExecutor,
+    ) -> Result<FileWithMeta> {
+        let is_whiteout = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(Self::is_whiteout_name)
+            .unwrap_or(false);
+        if !is_whiteout {
+            for root in roots {
+                match Self::lookup(root, path) {
+                    Lookup::Found(full_path) => {
+                        return FileWithMeta::open_blocking(full_path, executor)
+                    }
+                    Lookup::Whiteout => break,
+                    Lookup::Missing => continue,
+                }
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound, "not found in any layer"))
+    }
+
+    /// merge the entries of the directory in all the layers.
+    fn read_dir_blocking(roots: &[PathBuf], path: &Path) -> Result<Vec<DirEntry>> {
+        let mut merged: BTreeMap<String, DirEntry> = BTreeMap::new();
+        let mut hidden: HashSet<String> = HashSet::new();
+        let mut found = false;
+        for root in roots {
+            let full_path = match Self::lookup(root, path) {
+                Lookup::Found(full_path) => full_path,
+                Lookup::Whiteout => break,
+                Lookup::Missing => continue,
+            };
+            if !full_path.is_dir() {
+                // the file shadows the directories of the lower layers.
+                break;
+            }
+            found = true;
+            let mut is_opaque = false;
+            for entry in DirEntry::read_dir_blocking(&full_path)? {
+                if entry.name == OPAQUE_WHITEOUT {
+                    is_opaque = true;
+                } else if let Some(name) = entry.name.strip_prefix(WHITEOUT_PREFIX) {
+                    hidden.insert(name.to_string());
+                } else if !hidden.contains(&entry.name) && !merged.contains_key(&entry.name) {
+                    merged.insert(entry.name.clone(), entry);
+                }
+            }
+            if is_opaque {
+                break;
+            }
+        }
+        if !found {
+            return Err(Error::new(ErrorKind::NotFound, "not found in any layer"));
+        }
+        Ok(merged.into_values().collect())
+    }
+}
+
+impl FileReaderOpener for OverlayFileReaderOpener {
+    type Output = FileWithMeta;
+
+    type Future = FileWithMetaFuture;
+
+    fn open<T: AsRef<Path>>(&self, path: T) -> Self::Future {
+        let roots = self.roots.clone();
+        let path = path.as_ref().to_path_buf();
+        let executor = self.executor.clone();
+        FileWithMetaFuture::spawn(&*self.executor, move || {
+            Self::open_blocking(&roots, &path, &executor)
+        })
+    }
+
+    fn read_dir<T: AsRef<Path>>(&self, path: T) -> ReadDirFuture {
+        let roots = self.roots.clone();
+        let path = path.as_ref().to_path_buf();
+        ReadDirFuture::spawn(&*self.executor, move || {
+            Self::read_dir_blocking(&roots, &path)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::TempRoot;
+
+    #[test]
+    fn test_overlay() {
+        let root = TempRoot::new("overlay");
+        root.mkdir("upper/dir")
+            .mkdir("lower/gone")
+            .write("lower/a.txt", "lower")
+            .write("upper/a.txt", "upper")
+            .write("lower/b.txt", "lower")
+            .write("upper/.wh.b.txt", "")
+            .write("lower/gone/c.txt", "lower")
+            .write("upper/.wh.gone", "")
+            .write("lower/dir/d.txt", "lower")
+            .write("upper/dir/e.txt", "upper");
+        let roots = vec![root.join("upper"), root.join("lower")];
+
+        let executor = default_executor();
+        let open =
+            |p: &str| OverlayFileReaderOpener::open_blocking(&roots, Path::new(p), &executor);
+        assert_eq!(open("a.txt").unwrap().size, 5);
+        assert!(open("b.txt").is_err());
+        assert!(open("gone/c.txt").is_err());
+        assert!(open(".wh.b.txt").is_err());
+        assert!(open("dir/d.txt").is_ok());
+
+        let names = |p: &str| {
+            OverlayFileReaderOpener::read_dir_blocking(&roots, Path::new(p))
+                .unwrap()
+                .into_iter()
+                .map(|e| e.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(""), vec!["a.txt", "dir"]);
+        assert_eq!(names("dir"), vec!["d.txt", "e.txt"]);
+    }
+}
@@ -51,7 +51,7 @@ impl HttpRange {
                 return Err(ParseError::InvalidRange);
             }
             let mut length = range_end.to_u64().ok_or(ParseError::InvalidRange)?;
-            if length == 0 {
+            if length == 0 || file_size == 0 {
                 return Ok(None);
             }
             if length > file_size {
@@ -63,7 +63,7 @@ impl HttpRange {
             }))
         } else {
             let start = range_start.to_u64().ok_or(ParseError::InvalidRange)?;
-            if start > file_size {
+            if start >= file_size {
                 return Ok(None);
             }
             let length = if range_end.is_empty() {
@@ -126,6 +126,50 @@ impl SliceEx for [u8] {
     }
 }
 
+/// The `Content-Range` of the partial write, e.g. `bytes 0-99/1000`, the complete length is
+/// none for the `*`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ContentRange {
+    pub(crate) start: u64,
+    pub(crate) length: u64,
+    pub(crate) complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    pub(crate) fn parse(header: &str) -> Result<Self> {
+        let header = header.as_bytes().trim();
+        let range = header
+            .strip_prefix(b"bytes ")
+            .ok_or(ParseError::InvalidRange)?;
+        let mut split = range.splitn(2, |s| *s == b'/');
+        let range = split.next().ok_or(ParseError::InvalidRange)?.trim();
+        let complete_length = split.next().ok_or(ParseError::InvalidRange)?.trim();
+        let mut split = range.splitn(2, |s| *s == b'-');
+        let start = split.next().and_then(|s| s.to_u64());
+        let end = split.next().and_then(|s| s.to_u64());
+        // the end after the last byte should be representable, the length never overflows.
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if start <= end && end < u64::MAX => (start, end),
+            _ => return Err(ParseError::InvalidRange),
+        };
+        let complete_length = match complete_length {
+            b"*" => None,
+            length => {
+                let length = length.to_u64().ok_or(ParseError::InvalidRange)?;
+                if end >= length {
+                    return Err(ParseError::InvalidRange);
+                }
+                Some(length)
+            }
+        };
+        Ok(Self {
+            start,
+            length: end - start + 1,
+            complete_length,
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::ParseError;
@@ -145,10 +189,42 @@ mod test {
         };
     }
 
+    #[test]
+    fn test_content_range() {
+        assert_eq!(
+            ContentRange::parse("bytes 10-19/100").unwrap(),
+            ContentRange {
+                start: 10,
+                length: 10,
+                complete_length: Some(100)
+            }
+        );
+        assert_eq!(
+            ContentRange::parse("bytes 0-0/*").unwrap().complete_length,
+            None
+        );
+        assert!(ContentRange::parse("bytes 10-9/100").is_err());
+        assert!(ContentRange::parse("bytes 0-100/100").is_err());
+        assert!(ContentRange::parse("bytes */100").is_err());
+        assert!(ContentRange::parse("bytes 0-18446744073709551615/*").is_err());
+        assert!(ContentRange::parse("bytes 1-18446744073709551615/*").is_err());
+        assert_eq!(
+            ContentRange::parse("bytes 18446744073709551613-18446744073709551614/*").unwrap(),
+            ContentRange {
+                start: u64::MAX - 2,
+                length: 2,
+                complete_length: None
+            }
+        );
+    }
+
     #[test]
     fn test_parse() {
         test_error!("", 0, Err(ParseError::InvalidRange));
         test_error!("", 100, Err(ParseError::InvalidRange));
+        test_error!("bytes=0-", 0, Err(ParseError::NoOverlap));
+        test_error!("bytes=-5", 0, Err(ParseError::NoOverlap));
+        test_error!("bytes=10-", 10, Err(ParseError::NoOverlap));
         test_range!(
             "bytes=-5",
             10,
@@ -1,74 +1,388 @@
-use hyper::{Method, Request};
+use hyper::{
+    header::{ACCEPT, ACCEPT_ENCODING, HOST},
+    http::uri::Authority,
+    Method, Request,
+};
 use std::future::Future;
-use std::io::{ErrorKind, Result};
-use std::task::{Context, Poll};
-use std::{path::PathBuf, pin::Pin};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+use std::{
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+};
+
+use crate::{
+    config::{Config, Encoding},
+    file::{DirEntry, FileReaderOpener, FileWithMeta},
+};
 
-use crate::file::{FileReaderOpener, FileWithMeta, FileWithMetaFuture, TokioFileReaderOpener};
 #[derive(Debug)]
 pub enum Resolved {
     NotFound,
     IsDirectory,
     MethodNotMatched,
+    // the `OPTIONS` of the path or the server.
+    Options,
     PermissionDenied,
-    Found(FileWithMeta),
+    // the backend has the file, but can't serve it, e.g. the content is corrupted.
+    Unavailable,
+    Found(ResolvedFile),
+    // the directory is requested without the trailing slash, redirect to the location.
+    Redirect(String),
+    // the entries of the directory without the index file.
+    Listing(Vec<DirEntry>),
 }
 
-pub(crate) struct RequestResolve {
-    opener_future: FileWithMetaFuture,
-    is_method_match: bool,
+/// the file to serve and how it's selected.
+#[derive(Debug)]
+pub struct ResolvedFile {
+    pub file: FileWithMeta,
+    // the path of the requested file, the content type is guessed by it.
+    pub path: PathBuf,
+    // the encoding of the precompressed file.
+    pub encoding: Option<Encoding>,
+    // the spa fallback is served for the missing page.
+    pub is_spa_fallback: bool,
 }
 
-fn decode_percents(string: &str) -> String {
+pub(crate) type ResolveFuture = Pin<Box<dyn Future<Output = Result<Resolved>> + Send>>;
+
+pub(crate) fn decode_percents(string: &str) -> String {
     percent_encoding::percent_decode_str(string)
         .decode_utf8_lossy()
         .into_owned()
 }
 
-impl RequestResolve {
-    pub fn resolve<B>(path: impl Into<PathBuf>, r: &Request<B>) -> Self {
-        let opener = TokioFileReaderOpener::new(path);
-        let mut uri_path = r.uri().path();
-        if uri_path.starts_with('/') {
-            uri_path = &uri_path[1..];
-        }
-        let opener_future = opener.open(decode_percents(uri_path));
-        let is_method_match = matches!(*r.method(), Method::GET | Method::HEAD);
-        RequestResolve {
-            opener_future,
-            is_method_match,
+/// normalize the decoded uri path to the relative path, the `..` can't escape the root.
+pub(crate) fn sanitize_path(path: &str) -> PathBuf {
+    let mut segs: Vec<&str> = Vec::new();
+    for seg in path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                segs.pop();
+            }
+            seg => {
+                // drop the segment with the separator or the prefix of the platform.
+                let mut comps = Path::new(seg).components();
+                if let (Some(Component::Normal(_)), None) = (comps.next(), comps.next()) {
+                    segs.push(seg);
+                }
+            }
         }
     }
+    segs.iter().collect()
+}
+
+/// the path component of the request without the host.
+pub(crate) const NO_HOST: &str = "_";
+
+/// the lowercase host of the request without the port, from the `:authority` or the `Host`.
+/// the host which is not the domain name or ipv4 address is `None`.
+pub(crate) fn request_host<B>(r: &Request<B>) -> Option<String> {
+    let authority = match r.uri().authority() {
+        Some(authority) => authority.clone(),
+        None => r
+            .headers()
+            .get(HOST)?
+            .to_str()
+            .ok()?
+            .parse::<Authority>()
+            .ok()?,
+    };
+    let host = authority.host().trim_end_matches('.').to_ascii_lowercase();
+    let is_valid = !host.is_empty()
+        && !host.starts_with('.')
+        && !host.contains("..")
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.');
+    is_valid.then_some(host)
+}
+
+/// the path of the opener for the request, the host of the request is the first component of
+/// the path for the virtual host opener.
+pub(crate) fn host_path<O, B>(opener: &O, r: &Request<B>, path: impl AsRef<Path>) -> PathBuf
+where
+    O: FileReaderOpener + ?Sized,
+{
+    if opener.is_virtual_host() {
+        let host = request_host(r).unwrap_or_else(|| NO_HOST.to_string());
+        return Path::new(&host).join(path);
+    }
+    path.as_ref().to_path_buf()
 }
 
-impl Future for RequestResolve {
-    type Output = Result<Resolved>;
+/// the path without the host of the virtual host opener, the reverse of the `host_path`.
+pub(crate) fn strip_host<'a, O>(opener: &O, path: &'a Path) -> &'a Path
+where
+    O: FileReaderOpener + ?Sized,
+{
+    if !opener.is_virtual_host() {
+        return path;
+    }
+    let mut comps = path.components();
+    comps.next();
+    comps.as_path()
+}
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let Self {
-            ref mut opener_future,
-            is_method_match,
-        } = *self;
-        if !is_method_match {
-            return Poll::Ready(Ok(Resolved::MethodNotMatched));
+/// map the error of the opener to the resolved.
+fn resolved_error(e: Error) -> Result<Resolved> {
+    match e.kind() {
+        ErrorKind::NotFound => Ok(Resolved::NotFound),
+        ErrorKind::PermissionDenied => Ok(Resolved::PermissionDenied),
+        ErrorKind::InvalidData => Ok(Resolved::Unavailable),
+        _ => Err(e),
+    }
+}
+
+/// the sandboxed path of the request for the write methods, prefixed with the host for the
+/// virtual host opener, none if the path is hidden.
+pub(crate) fn request_path<O, B>(opener: &O, config: &Config, r: &Request<B>) -> Option<PathBuf>
+where
+    O: FileReaderOpener,
+{
+    sandbox_path(opener, config, r, r.uri().path())
+}
+
+/// the sandboxed path of the encoded uri path under the host of the request, e.g. the path of
+/// the `Destination`.
+pub(crate) fn sandbox_path<O, B>(
+    opener: &O,
+    config: &Config,
+    r: &Request<B>,
+    uri_path: &str,
+) -> Option<PathBuf>
+where
+    O: FileReaderOpener,
+{
+    let path = sanitize_path(&decode_percents(uri_path));
+    if config.is_hidden(&path) {
+        return None;
+    }
+    Some(host_path(opener, r, path))
+}
+
+/// resolve the file of the request, the request is not borrowed by the future.
+pub(crate) fn resolve<O, B>(opener: &Arc<O>, config: &Arc<Config>, r: &Request<B>) -> ResolveFuture
+where
+    O: FileReaderOpener,
+{
+    if r.method() == Method::OPTIONS {
+        return Box::pin(std::future::ready(Ok(Resolved::Options)));
+    }
+    if !matches!(*r.method(), Method::GET | Method::HEAD) {
+        return Box::pin(std::future::ready(Ok(Resolved::MethodNotMatched)));
+    }
+    let uri_path = r.uri().path();
+    let decoded_path = decode_percents(uri_path);
+    let path = sanitize_path(&decoded_path);
+    if config.is_hidden(&path) {
+        return Box::pin(std::future::ready(Ok(Resolved::NotFound)));
+    }
+    // the fallback is the navigation of the browser, always the `GET`.
+    let is_navigation = r.method() == Method::GET && accepts_html(r);
+    let spa_path = match config.spa_fallback {
+        Some(ref fallback) if is_navigation && config.is_spa_path(&decoded_path) => {
+            Some(host_path(&**opener, r, sanitize_path(fallback)))
         }
-        let file_with_meta = match Pin::new(opener_future).poll(cx) {
-            Poll::Ready(Ok(r)) => r,
-            Poll::Ready(Err(e)) => {
-                let rs = match e.kind() {
-                    ErrorKind::NotFound => Ok(Resolved::NotFound),
-                    ErrorKind::PermissionDenied => Ok(Resolved::PermissionDenied),
-                    _ => Err(e),
-                };
-
-                return Poll::Ready(rs);
-            }
-            Poll::Pending => return Poll::Pending,
+        _ => None,
+    };
+    let path = host_path(&**opener, r, path);
+    // the location of the directory with the trailing slash.
+    let location = (!uri_path.ends_with('/')).then(|| match r.uri().query() {
+        Some(query) => format!("{uri_path}/?{query}"),
+        None => format!("{uri_path}/"),
+    });
+    let accept_encoding = r
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let resolver = Resolver {
+        opener: opener.clone(),
+        config: config.clone(),
+        accept_encoding,
+    };
+    Box::pin(async move {
+        let resolved = resolver.resolve(path, location).await?;
+        match (resolved, spa_path) {
+            (Resolved::NotFound, Some(spa_path)) => resolver.resolve_spa(spa_path).await,
+            (resolved, _) => Ok(resolved),
+        }
+    })
+}
+
+/// the request accepts the html explicitly, e.g. the navigation of the browser.
+fn accepts_html<B>(r: &Request<B>) -> bool {
+    r.headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let is_html = parts.next() == Some("text/html");
+            is_html && !parts.any(|p| matches!(p, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"))
+        })
+}
+
+struct Resolver<O> {
+    opener: Arc<O>,
+    config: Arc<Config>,
+    accept_encoding: String,
+}
+
+impl<O: FileReaderOpener> Resolver<O> {
+    async fn open(&self, path: &Path) -> Result<FileWithMeta> {
+        self.opener.open(path).await.map(Into::into)
+    }
+
+    async fn resolve(&self, path: PathBuf, location: Option<String>) -> Result<Resolved> {
+        let file = match self.open(&path).await {
+            Ok(file) => file,
+            Err(e) => return resolved_error(e),
         };
-        if file_with_meta.is_dir {
-            Poll::Ready(Ok(Resolved::IsDirectory))
+        if file.is_dir {
+            self.resolve_dir(path, location).await
         } else {
-            Poll::Ready(Ok(Resolved::Found(file_with_meta)))
+            self.resolve_file(path, file).await
+        }
+    }
+
+    async fn resolve_dir(&self, path: PathBuf, location: Option<String>) -> Result<Resolved> {
+        let config = &self.config;
+        if config.index_files.is_empty() && !config.listing {
+            return Ok(Resolved::IsDirectory);
+        }
+        // the relative links in the index and listing need the trailing slash.
+        if let Some(location) = location {
+            return Ok(Resolved::Redirect(location));
+        }
+        for index in config.index_files.iter() {
+            let index_path = path.join(index);
+            match self.open(&index_path).await {
+                Ok(file) if !file.is_dir => return self.resolve_file(index_path, file).await,
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return resolved_error(e),
+            }
+        }
+        if !config.listing {
+            return Ok(Resolved::IsDirectory);
+        }
+        match self.opener.read_dir(&path).await {
+            Ok(mut entries) => {
+                let dir = strip_host(&*self.opener, &path);
+                entries.retain(|e| !config.is_hidden(&dir.join(&e.name)));
+                entries.truncate(config.max_listing_entries);
+                Ok(Resolved::Listing(entries))
+            }
+            Err(e) if e.kind() == ErrorKind::Unsupported => Ok(Resolved::IsDirectory),
+            Err(e) => resolved_error(e),
+        }
+    }
+
+    async fn resolve_spa(&self, spa_path: PathBuf) -> Result<Resolved> {
+        match self.open(&spa_path).await {
+            Ok(file) if !file.is_dir => match self.resolve_file(spa_path, file).await? {
+                Resolved::Found(mut f) => {
+                    f.is_spa_fallback = true;
+                    Ok(Resolved::Found(f))
+                }
+                resolved => Ok(resolved),
+            },
+            _ => Ok(Resolved::NotFound),
         }
     }
+
+    async fn resolve_file(&self, path: PathBuf, file: FileWithMeta) -> Result<Resolved> {
+        for encoding in self.config.precompressed.iter() {
+            if !encoding.is_accepted(&self.accept_encoding) {
+                continue;
+            }
+            let mut encoded_path = path.clone().into_os_string();
+            encoded_path.push(".");
+            encoded_path.push(encoding.extension());
+            match self.open(Path::new(&encoded_path)).await {
+                Ok(encoded) if !encoded.is_dir => {
+                    return Ok(Resolved::Found(ResolvedFile {
+                        file: encoded,
+                        path,
+                        encoding: Some(*encoding),
+                        is_spa_fallback: false,
+                    }))
+                }
+                _ => continue,
+            }
+        }
+        Ok(Resolved::Found(ResolvedFile {
+            file,
+            path,
+            encoding: None,
+            is_spa_fallback: false,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+    use hyper::{header, StatusCode};
+
+    #[test]
+    fn test_request_host() {
+        let host = |uri: &str, host: Option<&str>| {
+            let mut builder = Request::get(uri);
+            if let Some(host) = host {
+                builder = builder.header(HOST, host);
+            }
+            request_host(&builder.body(()).unwrap())
+        };
+        assert_eq!(host("/", Some("Example.COM:8080")).unwrap(), "example.com");
+        assert_eq!(
+            host("http://a.example.com/x", None).unwrap(),
+            "a.example.com"
+        );
+        assert_eq!(host("/", Some("example.com.")).unwrap(), "example.com");
+        assert!(host("/", None).is_none());
+        assert!(host("/", Some("..")).is_none());
+        assert!(host("/", Some("a_b")).is_none());
+        assert!(host("/", Some("[::1]:80")).is_none());
+    }
+
+    #[test]
+    fn test_spa_fallback() {
+        let root = TempRoot::new("spa");
+        root.write("index.html", "<html></html>");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .spa_fallback("index.html")
+            .build();
+        runtime().block_on(async {
+            let get = |path: &str, accept: &str| request("GET", path, &[("accept", accept)], "");
+            let resp = call(&mut svc, get("/settings/profile", "text/html,*/*;q=0.8")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-cache");
+            let resp = call(&mut svc, get("/assets/app.js", "text/html")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let resp = call(&mut svc, get("/api/users", "application/json")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let head = request("HEAD", "/settings/profile", &[("accept", "text/html")], "");
+            let resp = call(&mut svc, head).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn test_sanitize_path() {
+        assert_eq!(sanitize_path("/a/./b//c"), Path::new("a/b/c"));
+        assert_eq!(sanitize_path("/../../etc/passwd"), Path::new("etc/passwd"));
+        assert_eq!(sanitize_path("/a/b/../../../c"), Path::new("c"));
+        assert_eq!(sanitize_path("/.."), Path::new(""));
+    }
 }
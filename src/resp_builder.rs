@@ -1,9 +1,14 @@
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use hyper::{header, http::Result, HeaderMap, Method, Request, Response, StatusCode};
+use hyper::{
+    header::{self, HeaderValue},
+    http::Result,
+    HeaderMap, Method, Request, Response, StatusCode,
+};
 
 use crate::{
     body::{Body, FileBytesStream, MultiRangeBytesStream, RangeBytesStream},
+    config::Encoding,
     file::FileWithMeta,
     range::HttpRange,
 };
@@ -11,6 +16,7 @@ use crate::{
 const VALID_MTIME: Duration = Duration::from_secs(2);
 const BOUNDARY_LEN: u64 = 35;
 const BOUNDARY_CHRS: &[u8] = b"abcdefghghijkmlnopqrstuvwxyz0123456789ABCDEFGHGHIJKMLNOPQRSTUVWXYZ";
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
 
 #[derive(Default, Debug, Clone)]
 pub struct ResponseBuilder {
@@ -20,7 +26,24 @@ pub struct ResponseBuilder {
     if_modified_since: Option<SystemTime>,
     // `If-Range` request header.
     if_range: Option<String>,
+    // `If-None-Match` request header.
+    if_none_match: Option<String>,
     is_head_method: bool,
+    content_type: Option<String>,
+    // the `Content-Disposition` of the file.
+    content_disposition: Option<HeaderValue>,
+    // the encoding of the precompressed file.
+    content_encoding: Option<Encoding>,
+    // the response varies by the `Accept-Encoding`.
+    vary_encoding: bool,
+    // the `Cache-Control` of the mutable file.
+    cache_control: Option<HeaderValue>,
+    // add the `Expires` by the `max-age` of the `Cache-Control`.
+    expires: bool,
+    // the request with more ranges is responded with the whole file, none is unlimited.
+    max_ranges: Option<usize>,
+    // the custom headers, never override the computed headers.
+    headers: HeaderMap,
 }
 
 impl ResponseBuilder {
@@ -30,12 +53,11 @@ impl ResponseBuilder {
 
     fn random_boundary() -> String {
         let duration = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
-        let mut rnd = duration.map(|d| d.as_secs()).unwrap();
+        let rnd = duration.map(|d| d.as_secs()).unwrap();
         let mut boundary_buf = vec![0u8; (BOUNDARY_LEN + 10) as _];
-        for iter in boundary_buf.iter_mut().skip(10) {
+        for (rnd, iter) in (rnd..).zip(boundary_buf.iter_mut().skip(10)) {
             let idx = (rnd % BOUNDARY_LEN) as usize;
             *iter = BOUNDARY_CHRS[idx];
-            rnd += 1;
         }
         boundary_buf[0..10].copy_from_slice(b"blockless:");
         String::from_utf8(boundary_buf).unwrap()
@@ -56,6 +78,7 @@ impl ResponseBuilder {
         self.range_header(headers.get(header::RANGE));
         self.if_modified_since_header(headers.get(header::IF_MODIFIED_SINCE));
         self.if_range_header(headers.get(header::IF_RANGE));
+        self.if_none_match_header(headers.get(header::IF_NONE_MATCH));
         self
     }
 
@@ -71,18 +94,131 @@ impl ResponseBuilder {
         self
     }
 
+    fn if_none_match_header(&mut self, value: Option<&header::HeaderValue>) -> &mut Self {
+        self.if_none_match = value.and_then(|v| v.to_str().ok()).map(String::from);
+        self
+    }
+
+    /// check the `If-None-Match` with the weak comparison.
+    fn is_none_match(&self, etag: &str) -> bool {
+        let if_none_match = match self.if_none_match {
+            Some(ref v) => v,
+            None => return true,
+        };
+        !if_none_match.split(',').map(str::trim).any(|tag| {
+            tag == "*"
+                || tag.strip_prefix("W/").unwrap_or(tag) == etag.strip_prefix("W/").unwrap_or(etag)
+        })
+    }
+
+    pub fn content_type(&mut self, content_type: Option<&str>) -> &mut Self {
+        self.content_type = content_type.map(String::from);
+        self
+    }
+
+    pub fn content_disposition(&mut self, value: Option<HeaderValue>) -> &mut Self {
+        self.content_disposition = value;
+        self
+    }
+
+    pub fn content_encoding(&mut self, encoding: Option<Encoding>) -> &mut Self {
+        self.content_encoding = encoding;
+        self
+    }
+
+    pub fn vary_encoding(&mut self, vary: bool) -> &mut Self {
+        self.vary_encoding = vary;
+        self
+    }
+
+    pub fn cache_control(&mut self, value: Option<HeaderValue>) -> &mut Self {
+        self.cache_control = value;
+        self
+    }
+
+    pub fn expires(&mut self, expires: bool) -> &mut Self {
+        self.expires = expires;
+        self
+    }
+
+    pub fn max_ranges(&mut self, max_ranges: usize) -> &mut Self {
+        self.max_ranges = Some(max_ranges);
+        self
+    }
+
+    pub fn headers(&mut self, headers: HeaderMap) -> &mut Self {
+        self.headers = headers;
+        self
+    }
+
     fn is_head_method(&mut self, method: &Method) -> &mut Self {
         self.is_head_method = method == Method::HEAD;
         self
     }
 
+    /// the `Expires` by the `max-age` of the `Cache-Control`, the `no-cache` and the `no-store`
+    /// are already expired.
+    fn expires_header(cache_control: &HeaderValue) -> Option<String> {
+        let mut max_age = None;
+        for directive in cache_control.to_str().ok()?.split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            if directive == "no-cache" || directive == "no-store" {
+                return Some(httpdate::fmt_http_date(UNIX_EPOCH));
+            }
+            if let Some(secs) = directive.strip_prefix("max-age=") {
+                max_age = secs.trim_matches('"').parse::<u64>().ok();
+            }
+        }
+        let expires = SystemTime::now() + Duration::from_secs(max_age?);
+        Some(httpdate::fmt_http_date(expires))
+    }
+
     fn content_range_header(range: &HttpRange, file_size: u64) -> String {
-        format!("bytes {}-{}/{}", range.start, range.length, file_size)
+        let end = range.start + range.length - 1;
+        format!("bytes {}-{}/{}", range.start, end, file_size)
     }
 
     pub fn build(&self, file: FileWithMeta) -> Result<Response<Body>> {
+        let mut resp = self.build_response(file)?;
+        let headers = resp.headers_mut();
+        for name in self.headers.keys() {
+            if headers.contains_key(name) {
+                continue;
+            }
+            for value in self.headers.get_all(name) {
+                headers.append(name, value.clone());
+            }
+        }
+        Ok(resp)
+    }
+
+    fn build_response(&self, file: FileWithMeta) -> Result<Response<Body>> {
         let file_size = file.size;
         let mut resp_builder = Response::builder();
+        let cache_control = if file.immutable {
+            Some(HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL))
+        } else {
+            self.cache_control.clone()
+        };
+        if let Some(cache_control) = cache_control {
+            if self.expires {
+                if let Some(expires) = Self::expires_header(&cache_control) {
+                    resp_builder = resp_builder.header(header::EXPIRES, expires);
+                }
+            }
+            resp_builder = resp_builder.header(header::CACHE_CONTROL, cache_control);
+        }
+        if self.vary_encoding {
+            resp_builder = resp_builder.header(header::VARY, "Accept-Encoding");
+        }
+        if let Some(ref etag) = file.etag {
+            resp_builder = resp_builder.header(header::ETAG, etag);
+            if !self.is_none_match(etag) {
+                return resp_builder
+                    .status(StatusCode::NOT_MODIFIED)
+                    .body(Body::Empty);
+            }
+        }
         let modified = file.modified.filter(|m| {
             m.duration_since(UNIX_EPOCH)
                 .ok()
@@ -92,7 +228,9 @@ impl ResponseBuilder {
         if let Some(modified) = modified {
             if let Ok(unix_time) = modified.duration_since(UNIX_EPOCH) {
                 let ims_unix_time = self.if_modified_since.map(|t| t.duration_since(UNIX_EPOCH));
-                if let Some(Ok(ims_unix_time)) = ims_unix_time {
+                // the `If-None-Match` take precedence over the `If-Modified-Since`.
+                let has_inm = self.if_none_match.is_some() && file.etag.is_some();
+                if let (Some(Ok(ims_unix_time)), false) = (ims_unix_time, has_inm) {
                     if unix_time.as_secs() <= ims_unix_time.as_secs() {
                         return resp_builder
                             .status(StatusCode::NOT_MODIFIED)
@@ -104,7 +242,24 @@ impl ResponseBuilder {
             resp_builder = resp_builder.header(header::LAST_MODIFIED, last_modified);
             resp_builder = resp_builder.header(header::ACCEPT_RANGES, "bytes");
         }
+        if let Some(encoding) = self.content_encoding {
+            resp_builder = resp_builder.header(header::CONTENT_ENCODING, encoding.name());
+        }
         let ranges = self.range.as_ref().map(|s| HttpRange::parse(s, file_size));
+        // too many ranges, ignore the `Range` and respond the whole file.
+        let ranges = ranges.filter(|r| match (r, self.max_ranges) {
+            (Ok(r), Some(max_ranges)) => r.len() <= max_ranges,
+            _ => true,
+        });
+        if let Some(ref content_type) = self.content_type {
+            let is_multi_range = matches!(ranges, Some(Ok(ref r)) if r.len() > 1);
+            if !is_multi_range || self.is_head_method {
+                resp_builder = resp_builder.header(header::CONTENT_TYPE, content_type);
+            }
+        }
+        if let Some(ref disposition) = self.content_disposition {
+            resp_builder = resp_builder.header(header::CONTENT_DISPOSITION, disposition);
+        }
         if self.is_head_method {
             resp_builder = resp_builder.header(header::CONTENT_LENGTH, format!("{}", file_size));
             return resp_builder.status(StatusCode::OK).body(Body::Empty);
@@ -119,14 +274,14 @@ impl ResponseBuilder {
                 }
             };
             let ranges_len = ranges.len();
-            
+
             #[allow(clippy::comparison_chain)]
             if ranges_len == 1 {
                 let range = &ranges[0];
                 let content_range_header = Self::content_range_header(range, file.size);
                 resp_builder = resp_builder
                     .header(header::CONTENT_RANGE, content_range_header)
-                    .header(header::CONTENT_LENGTH, file_size);
+                    .header(header::CONTENT_LENGTH, range.length);
                 let stream = RangeBytesStream::new_with_range(file.into(), range);
                 return resp_builder
                     .status(StatusCode::PARTIAL_CONTENT)
@@ -134,7 +289,11 @@ impl ResponseBuilder {
             } else if ranges_len > 1 {
                 let boundary = Self::random_boundary();
                 let content_type = format!("multipart/byteranges; boundary={}", &boundary);
-                let stream = MultiRangeBytesStream::new(file.into(), ranges, boundary, file_size);
+                let mut stream =
+                    MultiRangeBytesStream::new(file.into(), ranges, boundary, file_size);
+                if let Some(ref content_type) = self.content_type {
+                    stream.set_content_type(content_type.clone());
+                }
                 resp_builder = resp_builder
                     .header(header::CONTENT_TYPE, content_type)
                     .header(header::CONTENT_LENGTH, stream.compute_body_len());
@@ -148,3 +307,85 @@ impl ResponseBuilder {
         resp_builder.status(StatusCode::OK).body(Body::Full(stream))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{body_string, call, request, runtime, TempRoot},
+        FileService, FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_range_headers() {
+        let root = TempRoot::new("range");
+        root.write("a.txt", "0123456789");
+        let mut svc = FileService::new(root.root());
+        runtime().block_on(async {
+            let get = |range: &str| request("GET", "/a.txt", &[("range", range)], "");
+            let resp = call(&mut svc, get("bytes=1-3")).await;
+            assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes 1-3/10");
+            assert_eq!(resp.headers()[header::CONTENT_LENGTH], "3");
+            let body = body_string(call(&mut svc, get("bytes=1-3,5-6")).await).await;
+            assert!(body.contains("Content-Range: bytes 1-3/10"));
+            assert!(body.contains("Content-Range: bytes 5-6/10"));
+            let resp = call(&mut svc, get("bytes=10-")).await;
+            assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+            // the local file has the entity tag, the client revalidates by it.
+            let etag = resp.headers()[header::ETAG].to_str().unwrap().to_string();
+            let req = request("GET", "/a.txt", &[("if-none-match", &etag)], "");
+            let resp = call(&mut svc, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        });
+    }
+
+    #[test]
+    fn test_cache_rules() {
+        let root = TempRoot::new("cache");
+        root.write("assets/app.1a2b.js", "js")
+            .write("index.html", "<html></html>")
+            .write("a.txt", "a");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .cache_control_path(
+                "/assets/**",
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            )
+            .cache_control_mime("text/html", HeaderValue::from_static("no-cache"))
+            .cache_control(HeaderValue::from_static("max-age=60"))
+            .expires(true)
+            .build();
+        runtime().block_on(async {
+            let req = request("GET", "/assets/app.1a2b.js", &[], "");
+            let resp = call(&mut svc, req).await;
+            let headers = resp.headers();
+            assert_eq!(
+                headers[header::CACHE_CONTROL],
+                "public, max-age=31536000, immutable"
+            );
+            let expires = headers[header::EXPIRES].to_str().unwrap();
+            assert!(httpdate::parse_http_date(expires).unwrap() > SystemTime::now());
+            let etag = headers[header::ETAG].to_str().unwrap().to_string();
+            let req = request(
+                "GET",
+                "/assets/app.1a2b.js",
+                &[("if-none-match", &etag)],
+                "",
+            );
+            let resp = call(&mut svc, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+            assert!(resp.headers().contains_key(header::EXPIRES));
+
+            let resp = call(&mut svc, request("GET", "/index.html", &[], "")).await;
+            assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-cache");
+            assert_eq!(
+                resp.headers()[header::EXPIRES],
+                "Thu, 01 Jan 1970 00:00:00 GMT"
+            );
+            let req = request("GET", "/a.txt", &[("range", "bytes=0-0")], "");
+            let resp = call(&mut svc, req).await;
+            assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(resp.headers()[header::CACHE_CONTROL], "max-age=60");
+        });
+    }
+}
@@ -0,0 +1,695 @@
+//! The resumable upload of the tus 1.0 protocol, the upload is the file under the endpoint,
+//! the state is the `.info` file next to it, so the upload survives the restart of the server.
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    body::HttpBody,
+    header::{self, HeaderValue},
+    http::response::Builder,
+    Method, Request, Response, StatusCode,
+};
+
+use crate::{
+    body::Body,
+    config::Config,
+    error::{BoxError, Error},
+    executor::BlockingFuture,
+    file::FileReaderOpener,
+    request_resolve::{decode_percents, host_path, sanitize_path},
+    write::{self, stream_body, target_meta, Streamed},
+};
+
+pub(crate) const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+const INFO_SUFFIX: &str = ".info";
+// the expired uploads are swept by the creation at most once in the interval.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// the request headers of the tus clients, allowed by the CORS preflight.
+#[cfg(feature = "cors")]
+pub(crate) const ALLOW_HEADERS: &[&str] = &[
+    "tus-resumable",
+    "upload-length",
+    "upload-offset",
+    "upload-metadata",
+];
+// the response headers read by the tus clients, exposed by the CORS.
+#[cfg(feature = "cors")]
+pub(crate) const EXPOSE_HEADERS: &[&str] = &[
+    "location",
+    "tus-resumable",
+    "tus-version",
+    "tus-extension",
+    "tus-max-size",
+    "upload-offset",
+    "upload-length",
+    "upload-expires",
+];
+
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The configuration of the tus endpoint.
+#[derive(Debug, Clone)]
+pub(crate) struct TusConfig {
+    // the path of the endpoint without the trailing slash, e.g. `/files`.
+    pub(crate) endpoint: String,
+    // the incomplete upload is removed after the expiration.
+    pub(crate) expiration: Option<Duration>,
+    // the uploads are being patched.
+    patching: Arc<Mutex<HashSet<PathBuf>>>,
+    // the seconds since the epoch of the last sweep.
+    swept_at: Arc<AtomicU64>,
+}
+
+enum Target {
+    Endpoint,
+    // the upload with the valid id.
+    Upload,
+}
+
+impl TusConfig {
+    pub(crate) fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: format!("/{}", endpoint.trim_matches('/')),
+            expiration: None,
+            patching: Arc::default(),
+            swept_at: Arc::default(),
+        }
+    }
+
+    /// the expired uploads should be swept, it's true once in the interval.
+    fn should_sweep(&self) -> bool {
+        if self.expiration.is_none() {
+            return false;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let swept_at = self.swept_at.load(Ordering::Relaxed);
+        let is_due = swept_at == 0 || now >= swept_at + SWEEP_INTERVAL.as_secs();
+        is_due
+            && self
+                .swept_at
+                .compare_exchange(swept_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    /// the request is for the endpoint or the upload under the endpoint.
+    pub(crate) fn is_endpoint(&self, uri_path: &str) -> bool {
+        self.target(uri_path).is_some()
+    }
+
+    /// the path is under the directory of the uploads, it's hidden from the other methods.
+    pub(crate) fn is_upload_path(&self, path: &Path) -> bool {
+        let dir = Path::new(self.endpoint.trim_start_matches('/'));
+        !dir.as_os_str().is_empty() && path.starts_with(dir)
+    }
+
+    fn target(&self, uri_path: &str) -> Option<Target> {
+        let rest = uri_path.strip_prefix(self.endpoint.trim_end_matches('/'))?;
+        match rest {
+            "" | "/" => Some(Target::Endpoint),
+            rest => {
+                let id = rest.strip_prefix('/')?;
+                let is_valid = !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric());
+                is_valid.then_some(Target::Upload)
+            }
+        }
+    }
+}
+
+/// remove the upload from the patching set when the patch is done.
+struct PatchGuard {
+    patching: Arc<Mutex<HashSet<PathBuf>>>,
+    path: PathBuf,
+}
+
+impl PatchGuard {
+    fn acquire(patching: &Arc<Mutex<HashSet<PathBuf>>>, path: &Path) -> Option<Self> {
+        let mut set = patching.lock().unwrap_or_else(|e| e.into_inner());
+        set.insert(path.to_path_buf()).then(|| PatchGuard {
+            patching: patching.clone(),
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PatchGuard {
+    fn drop(&mut self) {
+        let mut set = self.patching.lock().unwrap_or_else(|e| e.into_inner());
+        set.remove(&self.path);
+    }
+}
+
+/// the state of the upload, stored as the `name: value` lines.
+#[derive(Debug, PartialEq)]
+struct UploadInfo {
+    length: u64,
+    metadata: Option<String>,
+    expires: Option<u64>,
+}
+
+impl UploadInfo {
+    fn encode(&self) -> String {
+        let mut info = format!("length: {}\n", self.length);
+        if let Some(ref metadata) = self.metadata {
+            info.push_str(&format!("metadata: {metadata}\n"));
+        }
+        if let Some(expires) = self.expires {
+            info.push_str(&format!("expires: {expires}\n"));
+        }
+        info
+    }
+
+    fn decode(info: &str) -> Option<Self> {
+        let mut length = None;
+        let mut metadata = None;
+        let mut expires = None;
+        for line in info.lines() {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim();
+            match name.trim() {
+                "length" => length = value.parse().ok(),
+                "metadata" => metadata = Some(value.to_string()),
+                "expires" => expires = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Self {
+            length: length?,
+            metadata,
+            expires,
+        })
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// the incomplete upload is expired.
+    fn is_expired(&self, offset: u64) -> bool {
+        let expires_at = self.expires_at();
+        offset < self.length && expires_at.map(|e| e <= SystemTime::now()).unwrap_or(false)
+    }
+}
+
+fn info_path(path: &Path) -> PathBuf {
+    let mut info = path.as_os_str().to_owned();
+    info.push(INFO_SUFFIX);
+    PathBuf::from(info)
+}
+
+fn new_upload_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let counter = UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{nanos:x}{:x}{counter:x}", std::process::id())
+}
+
+/// load the info and the offset of the upload, the expired upload is removed.
+fn load_blocking(path: &Path) -> io::Result<Option<(UploadInfo, u64)>> {
+    let info = match fs::read_to_string(info_path(path)) {
+        Ok(info) => info,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let info = UploadInfo::decode(&info)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid upload info"))?;
+    let offset = match target_meta(path)? {
+        Some(meta) => meta.len(),
+        None => return Ok(None),
+    };
+    if info.is_expired(offset) {
+        remove_blocking(path)?;
+        return Ok(None);
+    }
+    Ok(Some((info, offset)))
+}
+
+/// remove the expired uploads in the directory, the uploads being patched are skipped.
+fn sweep_blocking(local_dir: &Path, dir: &Path, patching: &Mutex<HashSet<PathBuf>>) {
+    let entries = match fs::read_dir(local_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let id = match name.to_str().and_then(|n| n.strip_suffix(INFO_SUFFIX)) {
+            Some(id) => id,
+            None => continue,
+        };
+        let set = patching.lock().unwrap_or_else(|e| e.into_inner());
+        if !set.contains(&dir.join(id)) {
+            // the error of the single upload doesn't stop the sweep.
+            let _ = load_blocking(&local_dir.join(id));
+        }
+    }
+}
+
+fn remove_blocking(path: &Path) -> io::Result<bool> {
+    let removed = match fs::remove_file(path) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(e),
+    };
+    match fs::remove_file(info_path(path)) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(removed),
+        Err(e) => Err(e),
+    }
+}
+
+fn response(status: StatusCode) -> Builder {
+    Response::builder()
+        .status(status)
+        .header("tus-resumable", TUS_VERSION)
+}
+
+fn with_expires(builder: Builder, info: &UploadInfo, offset: u64) -> Builder {
+    match info.expires_at() {
+        Some(expires) if offset < info.length => {
+            builder.header("upload-expires", httpdate::fmt_http_date(expires))
+        }
+        _ => builder,
+    }
+}
+
+fn header_u64<B>(request: &Request<B>, name: &str) -> Option<u64> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// handle the request of the tus endpoint.
+pub(crate) async fn handle<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let tus = match config.tus {
+        Some(ref tus) => tus.clone(),
+        None => return write::status(StatusCode::NOT_FOUND),
+    };
+    let target = match tus.target(head.uri().path()) {
+        Some(target) => target,
+        None => return write::status(StatusCode::NOT_FOUND),
+    };
+    if head.method() == Method::OPTIONS {
+        let mut builder = response(StatusCode::NO_CONTENT)
+            .header("tus-version", TUS_VERSION)
+            .header("tus-extension", TUS_EXTENSIONS);
+        if let Some(max_size) = config.max_upload_size {
+            builder = builder.header("tus-max-size", max_size);
+        }
+        return Ok(builder.body(Body::Empty)?);
+    }
+    let resumable = head.headers().get("tus-resumable");
+    if resumable.map(|v| v != TUS_VERSION).unwrap_or(true) {
+        return Ok(response(StatusCode::PRECONDITION_FAILED)
+            .header("tus-version", TUS_VERSION)
+            .body(Body::Empty)?);
+    }
+    // the uploads are hidden by the config, so the path is not sandboxed by it.
+    let path = sanitize_path(&decode_percents(head.uri().path()));
+    let path = host_path(&*opener, head, path);
+    let resp = match (target, head.method()) {
+        (Target::Endpoint, &Method::POST) => create(opener, &config, &tus, head, path).await?,
+        (Target::Upload, &Method::HEAD) => offset(opener, path).await?,
+        (Target::Upload, &Method::PATCH) => patch(opener, &tus, head, path, body).await?,
+        (Target::Upload, &Method::DELETE) => terminate(opener, path).await?,
+        _ => response(StatusCode::METHOD_NOT_ALLOWED).body(Body::Empty)?,
+    };
+    Ok(resp)
+}
+
+/// the creation extension, the empty upload is created with the length.
+async fn create<O: FileReaderOpener>(
+    opener: Arc<O>,
+    config: &Config,
+    tus: &TusConfig,
+    head: &Request<()>,
+    dir: PathBuf,
+) -> Result<Response<Body>, Error> {
+    let length = match header_u64(head, "upload-length") {
+        Some(length) => length,
+        None => return Ok(response(StatusCode::BAD_REQUEST).body(Body::Empty)?),
+    };
+    if config.max_upload_size.map(|m| length > m).unwrap_or(false) {
+        return Ok(response(StatusCode::PAYLOAD_TOO_LARGE).body(Body::Empty)?);
+    }
+    let metadata = head
+        .headers()
+        .get("upload-metadata")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let expires = tus.expiration.and_then(|e| {
+        let expires = SystemTime::now() + e;
+        expires.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+    });
+    let info = UploadInfo {
+        length,
+        metadata,
+        expires,
+    };
+    let id = new_upload_id();
+    let path = dir.join(&id);
+    let executor = opener.blocking_executor();
+    let encoded = info.encode();
+    let create_dirs = config.create_dirs;
+    let sweep = tus.should_sweep().then(|| tus.patching.clone());
+    let created = BlockingFuture::spawn(&*executor, move || {
+        let path = match opener.local_path(&path) {
+            Some(path) => path,
+            None => return Ok(Err(StatusCode::METHOD_NOT_ALLOWED)),
+        };
+        if let Some(parent) = path.parent() {
+            if !parent.is_dir() {
+                if !create_dirs {
+                    return Ok(Err(StatusCode::CONFLICT));
+                }
+                fs::create_dir_all(parent)?;
+            }
+            // the expired upload is never touched again by the client.
+            if let Some(ref patching) = sweep {
+                sweep_blocking(parent, &dir, patching);
+            }
+        }
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        fs::write(info_path(&path), encoded)?;
+        Ok(Ok(()))
+    })
+    .await?;
+    if let Err(status) = created {
+        return Ok(response(status).body(Body::Empty)?);
+    }
+    let location = format!("{}/{id}", tus.endpoint.trim_end_matches('/'));
+    let builder = response(StatusCode::CREATED).header(header::LOCATION, location);
+    Ok(with_expires(builder, &info, 0).body(Body::Empty)?)
+}
+
+/// the offset of the upload, the client resumes the upload from it.
+async fn offset<O: FileReaderOpener>(
+    opener: Arc<O>,
+    path: PathBuf,
+) -> Result<Response<Body>, Error> {
+    let executor = opener.blocking_executor();
+    let loaded = BlockingFuture::spawn(&*executor, move || match opener.local_path(&path) {
+        Some(path) => load_blocking(&path),
+        None => Ok(None),
+    })
+    .await?;
+    let (info, offset) = match loaded {
+        Some(loaded) => loaded,
+        None => return Ok(response(StatusCode::NOT_FOUND).body(Body::Empty)?),
+    };
+    let mut builder = response(StatusCode::OK)
+        .header("upload-offset", offset)
+        .header("upload-length", info.length)
+        .header(header::CACHE_CONTROL, "no-store");
+    if let Some(metadata) = info.metadata.as_deref() {
+        if let Ok(metadata) = HeaderValue::from_str(metadata) {
+            builder = builder.header("upload-metadata", metadata);
+        }
+    }
+    Ok(with_expires(builder, &info, offset).body(Body::Empty)?)
+}
+
+/// append the body to the upload at the offset, the received data is kept when the body is
+/// interrupted.
+async fn patch<O, B>(
+    opener: Arc<O>,
+    tus: &TusConfig,
+    head: &Request<()>,
+    path: PathBuf,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let content_type = head
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if content_type != Some(OFFSET_OCTET_STREAM) {
+        return Ok(response(StatusCode::UNSUPPORTED_MEDIA_TYPE).body(Body::Empty)?);
+    }
+    let offset = match header_u64(head, "upload-offset") {
+        Some(offset) => offset,
+        None => return Ok(response(StatusCode::BAD_REQUEST).body(Body::Empty)?),
+    };
+    let _guard = match PatchGuard::acquire(&tus.patching, &path) {
+        Some(guard) => guard,
+        None => return Ok(response(StatusCode::CONFLICT).body(Body::Empty)?),
+    };
+    let executor = opener.blocking_executor();
+    let opened = BlockingFuture::spawn(&*executor, move || {
+        let path = match opener.local_path(&path) {
+            Some(path) => path,
+            None => return Ok(Err(StatusCode::NOT_FOUND)),
+        };
+        let (info, current) = match load_blocking(&path)? {
+            Some(loaded) => loaded,
+            None => return Ok(Err(StatusCode::NOT_FOUND)),
+        };
+        if current != offset {
+            return Ok(Err(StatusCode::CONFLICT));
+        }
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Ok((file, info)))
+    })
+    .await?;
+    let (file, info) = match opened {
+        Ok(opened) => opened,
+        Err(s) => return Ok(response(s).body(Body::Empty)?),
+    };
+    let limit = info.length.saturating_sub(offset);
+    let streamed = stream_body(
+        &executor,
+        file,
+        body,
+        Some(limit),
+        StatusCode::PAYLOAD_TOO_LARGE,
+    );
+    let (mut file, buf, written, status) = match streamed.await? {
+        Streamed::Done {
+            file,
+            buf,
+            written,
+            complete,
+        } => {
+            let status = if complete {
+                StatusCode::NO_CONTENT
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            (file, buf, written, status)
+        }
+        Streamed::Status(s) => return Ok(response(s).body(Body::Empty)?),
+    };
+    BlockingFuture::spawn(&*executor, move || {
+        file.write_all(&buf)?;
+        file.sync_all()
+    })
+    .await?;
+    let offset = offset + written;
+    let builder = response(status).header("upload-offset", offset);
+    Ok(with_expires(builder, &info, offset).body(Body::Empty)?)
+}
+
+/// the termination extension, the upload and the info are removed.
+async fn terminate<O: FileReaderOpener>(
+    opener: Arc<O>,
+    path: PathBuf,
+) -> Result<Response<Body>, Error> {
+    let executor = opener.blocking_executor();
+    let removed = BlockingFuture::spawn(&*executor, move || match opener.local_path(&path) {
+        Some(path) => remove_blocking(&path),
+        None => Ok(false),
+    })
+    .await?;
+    let status = if removed {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    };
+    Ok(response(status).body(Body::Empty)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_upload_info() {
+        let info = UploadInfo {
+            length: 10,
+            metadata: Some("filename d29ybGQ=".to_string()),
+            expires: Some(1),
+        };
+        assert_eq!(UploadInfo::decode(&info.encode()).unwrap(), info);
+        assert!(info.is_expired(5));
+        assert!(!info.is_expired(10));
+        let tus = TusConfig::new("/files/");
+        assert!(matches!(tus.target("/files"), Some(Target::Endpoint)));
+        assert!(matches!(tus.target("/files/abc1"), Some(Target::Upload)));
+        assert!(tus.target("/files/a/b").is_none());
+        assert!(tus.target("/filesx").is_none());
+    }
+
+    #[test]
+    fn test_tus() {
+        let root = TempRoot::new("tus");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .tus("/files")
+            .tus_expiration(Duration::from_secs(3600))
+            .build();
+        let mut plain = svc.clone();
+        runtime().block_on(async {
+            let mut tus =
+                |method: &str, uri: &str, headers: &[(&str, &str)], body: &'static str| {
+                    let mut req = request(method, uri, headers, body);
+                    let version = HeaderValue::from_static("1.0.0");
+                    req.headers_mut().insert("tus-resumable", version);
+                    call(&mut svc, req)
+                };
+            // the directory of the uploads is not created without the `create_dirs`.
+            let resp = tus("POST", "/files", &[("upload-length", "10")], "").await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            root.mkdir("files");
+            let resp = tus("POST", "/files", &[("upload-length", "10")], "").await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert!(resp.headers().contains_key("upload-expires"));
+            let location = resp.headers()[header::LOCATION].to_str().unwrap();
+            let location = location.to_string();
+            let patch = |offset| {
+                [
+                    ("content-type", "application/offset+octet-stream"),
+                    ("upload-offset", offset),
+                ]
+            };
+            let resp = tus("PATCH", &location, &patch("0"), "hello").await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(resp.headers()["upload-offset"], "5");
+            let resp = tus("HEAD", &location, &[], "").await;
+            assert_eq!(resp.headers()["upload-offset"], "5");
+            assert_eq!(resp.headers()["upload-length"], "10");
+            let resp = tus("PATCH", &location, &patch("0"), "hello").await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            let resp = tus("PATCH", &location, &patch("5"), "world").await;
+            assert_eq!(resp.headers()["upload-offset"], "10");
+            assert!(!resp.headers().contains_key("upload-expires"));
+            let path = root.join(location.trim_start_matches('/'));
+            assert_eq!(fs::read(&path).unwrap(), b"helloworld");
+            // the uploads are hidden from the other methods.
+            let info = format!("{location}.info");
+            let resp = call(&mut plain, request("GET", &info, &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let resp = call(&mut plain, request("PUT", &info, &[], "length: 1\n")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let resp = call(&mut plain, request("DELETE", &info, &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            assert!(root.join("files").read_dir().unwrap().count() == 2);
+            let resp = tus("DELETE", &location, &[], "").await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert!(!path.exists());
+            let resp = call(&mut svc, request("POST", "/files", &[], "")).await;
+            assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+        });
+    }
+
+    #[test]
+    fn test_tus_sweep() {
+        let root = TempRoot::new("tus-sweep");
+        let expired = UploadInfo {
+            length: 10,
+            metadata: None,
+            expires: Some(1),
+        };
+        root.write("files/abc1", "hello");
+        root.write("files/abc1.info", expired.encode());
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .tus("/files")
+            .tus_expiration(Duration::from_secs(3600))
+            .build();
+        runtime().block_on(async {
+            let headers = [("tus-resumable", "1.0.0"), ("upload-length", "10")];
+            let resp = call(&mut svc, request("POST", "/files", &headers, "")).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+        });
+        // the expired upload is removed by the creation of the other one.
+        assert!(!root.join("files/abc1").exists());
+        assert!(!root.join("files/abc1.info").exists());
+        assert_eq!(root.join("files").read_dir().unwrap().count(), 2);
+    }
+
+    #[cfg(feature = "cors")]
+    #[test]
+    fn test_tus_cors() {
+        let root = TempRoot::new("tus-cors");
+        root.mkdir("files");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .tus("/files")
+            .cors_origin("https://app.example.com")
+            .build();
+        runtime().block_on(async {
+            let preflight = [
+                ("origin", "https://app.example.com"),
+                ("access-control-request-method", "POST"),
+                (
+                    "access-control-request-headers",
+                    "tus-resumable, upload-length",
+                ),
+            ];
+            let resp = call(&mut svc, request("OPTIONS", "/files", &preflight, "")).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let headers = [
+                ("origin", "https://app.example.com"),
+                ("tus-resumable", "1.0.0"),
+                ("upload-length", "1"),
+            ];
+            let resp = call(&mut svc, request("POST", "/files", &headers, "")).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            let expose = resp.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS].to_str();
+            let expose = expose.unwrap();
+            assert!(expose.contains("location") && expose.contains("upload-offset"));
+        });
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    executor::{default_executor, BlockingExecutor, BlockingFuture, SharedExecutor},
+    file::{DirEntry, FileReaderOpener, FileWithMeta, FileWithMetaFuture, ReadDirFuture},
+    request_resolve::{sanitize_path, NO_HOST},
+};
+
+/// the placeholder of the host in the layout.
+const HOST_PLACEHOLDER: &str = "{host}";
+
+/// The opener select the document root by the host of the request, the root of the host is
+/// `<base>/<host>/` by default, or the custom layout, e.g. `{host}/public`. The request of the
+/// unknown host or without the host is served by the default host if it's set, otherwise not found.
+///
+/// ```ignore
+/// let opener = VirtualHostFileReaderOpener::new("/srv/sites").default_host("default");
+/// let server = builder.serve(FileServiceMaker::with_opener(opener));
+/// ```
+pub struct VirtualHostFileReaderOpener {
+    base: Arc<PathBuf>,
+    layout: Arc<String>,
+    default_host: Option<Arc<String>>,
+    // the hosts with the existing root, the lookup of them has no syscall.
+    known_hosts: Arc<Mutex<HashSet<String>>>,
+    executor: SharedExecutor,
+}
+
+impl VirtualHostFileReaderOpener {
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: Arc::new(base.into()),
+            layout: Arc::new(HOST_PLACEHOLDER.to_string()),
+            default_host: None,
+            known_hosts: Default::default(),
+            executor: default_executor(),
+        }
+    }
+
+    /// the directory of the host under the base, the `{host}` is replaced by the host.
+    pub fn layout(mut self, layout: impl Into<String>) -> Self {
+        self.layout = Arc::new(layout.into());
+        self
+    }
+
+    /// the host serves the request of the unknown host.
+    pub fn default_host(mut self, host: impl Into<String>) -> Self {
+        self.default_host = Some(Arc::new(host.into().to_ascii_lowercase()));
+        self
+    }
+
+    /// the executor run the blocking file operations, default is the tokio executor.
+    pub fn executor(mut self, executor: impl BlockingExecutor) -> Self {
+        self.executor = Arc::new(executor);
+        self
+    }
+
+    /// split the host and the path relative to the root of the host.
+    fn split(path: &Path) -> (String, PathBuf) {
+        let mut comps = path.iter();
+        let host = comps
+            .next()
+            .and_then(|h| h.to_str())
+            .unwrap_or(NO_HOST)
+            .to_string();
+        (host, comps.collect())
+    }
+
+    fn host_root(base: &Path, layout: &str, host: &str) -> PathBuf {
+        base.join(sanitize_path(&layout.replace(HOST_PLACEHOLDER, host)))
+    }
+
+    /// the root of the host, fallback to the default host if the host's root not exists.
+    fn root_blocking(
+        base: &Path,
+        layout: &str,
+        default_host: Option<&str>,
+        known_hosts: &Mutex<HashSet<String>>,
+        host: &str,
+    ) -> Result<PathBuf> {
+        if host != NO_HOST {
+            let root = Self::host_root(base, layout, host);
+            if known_hosts.lock().unwrap().contains(host) {
+                return Ok(root);
+            }
+            if root.is_dir() {
+                known_hosts.lock().unwrap().insert(host.to_string());
+                return Ok(root);
+            }
+        }
+        match default_host {
+            Some(host) => Ok(Self::host_root(base, layout, host)),
+            None => Err(Error::new(ErrorKind::NotFound, "unknown host")),
+        }
+    }
+
+    fn spawn<T, F>(&self, path: &Path, f: F) -> BlockingFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(PathBuf, &SharedExecutor) -> Result<T> + Send + 'static,
+    {
+        let base = self.base.clone();
+        let layout = self.layout.clone();
+        let default_host = self.default_host.clone();
+        let known_hosts = self.known_hosts.clone();
+        let executor = self.executor.clone();
+        let (host, path) = Self::split(path);
+        BlockingFuture::spawn(&*self.executor, move || {
+            let default_host = default_host.as_deref().map(|h| h.as_str());
+            let root = Self::root_blocking(&base, &layout, default_host, &known_hosts, &host)?;
+            f(root.join(path), &executor)
+        })
+    }
+}
+
+impl FileReaderOpener for VirtualHostFileReaderOpener {
+    type Output = FileWithMeta;
+
+    type Future = FileWithMetaFuture;
+
+    fn open<T: AsRef<Path>>(&self, path: T) -> Self::Future {
+        self.spawn(path.as_ref(), |full_path, executor| {
+            FileWithMeta::open_blocking(full_path, executor)
+        })
+    }
+
+    fn read_dir<T: AsRef<Path>>(&self, path: T) -> ReadDirFuture {
+        self.spawn(path.as_ref(), |full_path, _| {
+            DirEntry::read_dir_blocking(full_path)
+        })
+    }
+
+    fn is_virtual_host(&self) -> bool {
+        true
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        let (host, path) = Self::split(path);
+        let default_host = self.default_host.as_deref().map(|h| h.as_str());
+        let known_hosts = &self.known_hosts;
+        let root = Self::root_blocking(&self.base, &self.layout, default_host, known_hosts, &host);
+        let root = root.ok()?;
+        Some(root.join(path))
+    }
+
+    fn blocking_executor(&self) -> SharedExecutor {
+        self.executor.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{body_string, call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+    use hyper::StatusCode;
+
+    #[test]
+    fn test_root() {
+        let root = TempRoot::new("vhost");
+        root.mkdir("a.example.com/public").mkdir("default/public");
+        let base = root.path();
+        let layout = "{host}/public";
+        let known_hosts = Mutex::default();
+        let root = |host: &str, default_host: Option<&str>| {
+            VirtualHostFileReaderOpener::root_blocking(
+                base,
+                layout,
+                default_host,
+                &known_hosts,
+                host,
+            )
+        };
+        assert_eq!(
+            root("a.example.com", None).unwrap(),
+            base.join("a.example.com/public")
+        );
+        assert!(root("b.example.com", None).is_err());
+        assert_eq!(
+            root("b.example.com", Some("default")).unwrap(),
+            base.join("default/public")
+        );
+        assert_eq!(
+            root(NO_HOST, Some("default")).unwrap(),
+            base.join("default/public")
+        );
+        // the known host is not checked again.
+        std::fs::remove_dir_all(base.join("a.example.com")).unwrap();
+        assert_eq!(
+            root("a.example.com", None).unwrap(),
+            base.join("a.example.com/public")
+        );
+    }
+
+    #[test]
+    fn test_host_files() {
+        let root = TempRoot::new("vhost-files");
+        root.write("a.example.com/index.html", "a")
+            .write("a.example.com/_headers", "/*.html\n  X-Site: a\n")
+            .write("a.example.com/404.html", "a missing")
+            .write("default/index.html", "default");
+        let opener = VirtualHostFileReaderOpener::new(root.path()).default_host("default");
+        let mut svc = FileServiceBuilder::with_opener(opener)
+            .headers_file(true)
+            .error_page(StatusCode::NOT_FOUND, "404.html")
+            .build();
+        runtime().block_on(async {
+            let host = [("host", "a.example.com"), ("accept", "text/html")];
+            let resp = call(&mut svc, request("GET", "/index.html", &host, "")).await;
+            assert_eq!(resp.headers()["x-site"], "a");
+            assert_eq!(body_string(resp).await, "a");
+            let resp = call(&mut svc, request("GET", "/missing", &host, "")).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            assert_eq!(body_string(resp).await, "a missing");
+            // the headers file of the host is not applied to the default host.
+            let host = [("host", "b.example.com")];
+            let resp = call(&mut svc, request("GET", "/index.html", &host, "")).await;
+            assert!(!resp.headers().contains_key("x-site"));
+            assert_eq!(body_string(resp).await, "default");
+        });
+    }
+}
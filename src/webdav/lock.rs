@@ -0,0 +1,410 @@
+//! The write locks of the WebDAV class 2, the locks are kept in the memory of the service and
+//! saved to the optional store after every change.
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::BuildHasher,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use super::xml;
+
+/// the timeout of the lock without the `Timeout` header.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3600);
+/// the longest timeout, the `Infinite` timeout is granted as the longest.
+pub(crate) const MAX_TIMEOUT: Duration = Duration::from_secs(24 * 3600);
+
+static TOKEN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The write lock of the resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    /// the lock token, e.g. `urn:uuid:...`.
+    pub token: String,
+    /// the sandboxed path of the lock root, prefixed with the host for the virtual host opener.
+    pub path: PathBuf,
+    /// the href of the lock root.
+    pub href: String,
+    /// the exclusive lock, or the shared lock.
+    pub exclusive: bool,
+    /// the lock covers the members of the collection.
+    pub deep: bool,
+    /// the xml of the lock owner, e.g. `<D:href>mailto:a@example.com</D:href>`.
+    pub owner: Option<String>,
+    pub timeout: Duration,
+    pub expires_at: SystemTime,
+}
+
+impl Lock {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at <= now
+    }
+
+    /// the lock applies to the path, the path is the root or the member of the deep lock.
+    pub(crate) fn covers(&self, path: &Path) -> bool {
+        self.path == path || (self.deep && path.starts_with(&self.path))
+    }
+
+    fn overlaps(&self, path: &Path, deep: bool) -> bool {
+        self.covers(path) || (deep && self.path.starts_with(path))
+    }
+
+    /// the `activelock` element of the lock discovery.
+    pub(crate) fn render(&self) -> String {
+        let scope = if self.exclusive {
+            "exclusive"
+        } else {
+            "shared"
+        };
+        let depth = if self.deep { "infinity" } else { "0" };
+        let remaining = self
+            .expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        format!(
+            "<D:activelock><D:locktype><D:write/></D:locktype>\
+             <D:lockscope><D:{scope}/></D:lockscope><D:depth>{depth}</D:depth>{}\
+             <D:timeout>Second-{}</D:timeout>\
+             <D:locktoken><D:href>{}</D:href></D:locktoken>\
+             <D:lockroot><D:href>{}</D:href></D:lockroot></D:activelock>",
+            self.owner
+                .as_ref()
+                .map(|o| format!("<D:owner>{o}</D:owner>"))
+                .unwrap_or_default(),
+            remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0),
+            xml::escape(&self.token),
+            xml::escape(&self.href),
+        )
+    }
+}
+
+/// The persistence of the WebDAV locks, the locks survive the restart of the server.
+pub trait LockStore: Send + Sync {
+    /// load the locks when the service is built, the expired locks are dropped.
+    fn load(&self) -> io::Result<Vec<Lock>>;
+
+    /// save the active locks after every change, called in the blocking thread.
+    fn save(&self, locks: &[Lock]) -> io::Result<()>;
+}
+
+/// the lock request conflicts with the active lock.
+#[derive(Debug)]
+pub(crate) struct Conflict;
+
+/// The locks of the service, shared by all the services of the maker.
+#[derive(Default)]
+pub(crate) struct LockManager {
+    locks: Mutex<HashMap<String, Lock>>,
+    store: Option<Arc<dyn LockStore>>,
+}
+
+impl fmt::Debug for LockManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockManager")
+            .field("locks", &self.locks.lock().unwrap().len())
+            .field("store", &self.store.is_some())
+            .finish()
+    }
+}
+
+impl LockManager {
+    /// load the locks from the store, the locks are dropped if the store fails.
+    pub(crate) fn with_store(store: Arc<dyn LockStore>) -> Self {
+        let now = SystemTime::now();
+        let locks = store
+            .load()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|l| !l.is_expired(now))
+            .map(|l| (l.token.clone(), l))
+            .collect();
+        Self {
+            locks: Mutex::new(locks),
+            store: Some(store),
+        }
+    }
+
+    /// the active locks, the expired locks are removed.
+    fn active(&self) -> std::sync::MutexGuard<'_, HashMap<String, Lock>> {
+        let mut locks = self.locks.lock().unwrap();
+        let now = SystemTime::now();
+        locks.retain(|_, l| !l.is_expired(now));
+        locks
+    }
+
+    fn save(&self, locks: &HashMap<String, Lock>) -> io::Result<()> {
+        match self.store {
+            Some(ref store) => store.save(&locks.values().cloned().collect::<Vec<_>>()),
+            None => Ok(()),
+        }
+    }
+
+    /// the active locks apply to the path.
+    pub(crate) fn covering(&self, path: &Path) -> Vec<Lock> {
+        self.active()
+            .values()
+            .filter(|l| l.covers(path))
+            .cloned()
+            .collect()
+    }
+
+    /// create the lock, the exclusive lock conflicts with any lock of the overlapped resources.
+    pub(crate) fn lock(&self, lock: Lock) -> io::Result<Result<Lock, Conflict>> {
+        let mut locks = self.active();
+        let is_conflict = locks
+            .values()
+            .any(|l| l.overlaps(&lock.path, lock.deep) && (l.exclusive || lock.exclusive));
+        if is_conflict {
+            return Ok(Err(Conflict));
+        }
+        locks.insert(lock.token.clone(), lock.clone());
+        self.save(&locks)?;
+        Ok(Ok(lock))
+    }
+
+    /// refresh the timeout of the lock applies to the path.
+    pub(crate) fn refresh(
+        &self,
+        token: &str,
+        path: &Path,
+        timeout: Duration,
+    ) -> io::Result<Option<Lock>> {
+        let mut locks = self.active();
+        let lock = match locks.get_mut(token) {
+            Some(lock) if lock.covers(path) => lock,
+            _ => return Ok(None),
+        };
+        lock.timeout = timeout;
+        lock.expires_at = SystemTime::now() + timeout;
+        let lock = lock.clone();
+        self.save(&locks)?;
+        Ok(Some(lock))
+    }
+
+    /// remove the lock applies to the path, false if the token is unknown.
+    pub(crate) fn unlock(&self, token: &str, path: &Path) -> io::Result<bool> {
+        let mut locks = self.active();
+        match locks.get(token) {
+            Some(lock) if lock.covers(path) => {}
+            _ => return Ok(false),
+        }
+        locks.remove(token);
+        self.save(&locks)?;
+        Ok(true)
+    }
+
+    /// remove the locks of the path and the members, e.g. the resource is deleted.
+    pub(crate) fn remove_under(&self, path: &Path) -> io::Result<()> {
+        let mut locks = self.active();
+        let len = locks.len();
+        locks.retain(|_, l| !l.path.starts_with(path));
+        if locks.len() != len {
+            self.save(&locks)?;
+        }
+        Ok(())
+    }
+
+    /// the path can be changed with the submitted tokens, every exclusive lock needs the token
+    /// and one of the shared locks needs the token. the deep change checks the member locks.
+    pub(crate) fn is_permitted(&self, path: &Path, deep: bool, tokens: &[&str]) -> bool {
+        let locks = self.active();
+        let mut shared = None;
+        for lock in locks.values() {
+            let in_scope = lock.covers(path) || (deep && lock.path.starts_with(path));
+            if !in_scope {
+                continue;
+            }
+            let submitted = tokens.contains(&lock.token.as_str());
+            if lock.exclusive {
+                if !submitted {
+                    return false;
+                }
+            } else {
+                shared = Some(shared.unwrap_or(false) || submitted);
+            }
+        }
+        shared.unwrap_or(true)
+    }
+}
+
+/// the new lock token, the random state of the std seeds the token.
+pub(crate) fn new_token() -> String {
+    let seed = RandomState::new();
+    let counter = TOKEN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let hash = |salt: u64| seed.hash_one((salt, counter, nanos, std::process::id()));
+    let (high, low) = (hash(0), hash(1));
+    format!(
+        "urn:uuid:{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xfff,
+        (low >> 48) & 0x3fff | 0x8000,
+        low & 0xffff_ffff_ffff,
+    )
+}
+
+/// the first supported timeout of the `Timeout` header, e.g. `Second-3600, Infinite`.
+pub(crate) fn parse_timeout(value: Option<&str>) -> Duration {
+    let value = match value {
+        Some(value) => value,
+        None => return DEFAULT_TIMEOUT,
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .find_map(|t| match t {
+            "Infinite" => Some(MAX_TIMEOUT),
+            t => t
+                .strip_prefix("Second-")
+                .and_then(|s| s.parse().ok())
+                .map(|s| Duration::from_secs(s).min(MAX_TIMEOUT)),
+        })
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// The state of the condition, the lock token or the entity tag.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum State {
+    Token(String),
+    ETag(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Condition {
+    pub(crate) not: bool,
+    pub(crate) state: State,
+}
+
+/// The list of the conditions, the tagged list applies to the resource.
+#[derive(Debug, PartialEq)]
+pub(crate) struct IfList {
+    pub(crate) resource: Option<String>,
+    pub(crate) conditions: Vec<Condition>,
+}
+
+/// The `If` header of the WebDAV.
+#[derive(Debug, PartialEq)]
+pub(crate) struct IfHeader {
+    pub(crate) lists: Vec<IfList>,
+}
+
+impl IfHeader {
+    /// parse the `If` header, none if it's invalid.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let mut lists = Vec::new();
+        let mut resource = None;
+        let mut rest = value.trim_start();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('<') {
+                let end = after.find('>')?;
+                resource = Some(after[..end].to_string());
+                rest = after[end + 1..].trim_start();
+                continue;
+            }
+            let mut after = rest.strip_prefix('(')?.trim_start();
+            let mut conditions = Vec::new();
+            while !after.starts_with(')') {
+                let not = match after.strip_prefix("Not") {
+                    Some(a) => {
+                        after = a.trim_start();
+                        true
+                    }
+                    None => false,
+                };
+                let (state, a) = if let Some(a) = after.strip_prefix('<') {
+                    let end = a.find('>')?;
+                    (State::Token(a[..end].to_string()), &a[end + 1..])
+                } else if let Some(a) = after.strip_prefix('[') {
+                    let end = a.find(']')?;
+                    (State::ETag(a[..end].to_string()), &a[end + 1..])
+                } else {
+                    return None;
+                };
+                conditions.push(Condition { not, state });
+                after = a.trim_start();
+            }
+            if conditions.is_empty() {
+                return None;
+            }
+            lists.push(IfList {
+                resource: resource.clone(),
+                conditions,
+            });
+            rest = after[1..].trim_start();
+        }
+        (!lists.is_empty()).then_some(Self { lists })
+    }
+
+    /// the lock tokens submitted by the header.
+    pub(crate) fn tokens(&self) -> Vec<&str> {
+        self.lists
+            .iter()
+            .flat_map(|l| l.conditions.iter())
+            .filter_map(|c| match c.state {
+                State::Token(ref token) => Some(token.as_str()),
+                State::ETag(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_if_header() {
+        let header =
+            IfHeader::parse("</a/b> (<urn:uuid:1> [\"tag\"]) (Not <DAV:no-lock>)  (<urn:uuid:2>)")
+                .unwrap();
+        assert_eq!(header.lists.len(), 3);
+        assert_eq!(header.lists[2].resource.as_deref(), Some("/a/b"));
+        assert!(header.lists[1].conditions[0].not);
+        assert_eq!(
+            header.lists[0].conditions[1].state,
+            State::ETag("\"tag\"".into())
+        );
+        assert_eq!(header.tokens(), ["urn:uuid:1", "DAV:no-lock", "urn:uuid:2"]);
+        assert!(IfHeader::parse("(<urn:uuid:1>").is_none());
+        assert!(IfHeader::parse("()").is_none());
+        assert_eq!(parse_timeout(Some("Infinite, Second-60")), MAX_TIMEOUT);
+        assert_eq!(parse_timeout(Some("Second-60")), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_lock_manager() {
+        let manager = LockManager::default();
+        let lock = |path: &str, exclusive, deep| Lock {
+            token: new_token(),
+            path: PathBuf::from(path),
+            href: format!("/{path}"),
+            exclusive,
+            deep,
+            owner: None,
+            timeout: DEFAULT_TIMEOUT,
+            expires_at: SystemTime::now() + DEFAULT_TIMEOUT,
+        };
+        let dir = manager.lock(lock("a", false, true)).unwrap().unwrap();
+        let file = manager.lock(lock("a/b", false, false)).unwrap().unwrap();
+        assert!(manager.lock(lock("a/b", true, false)).unwrap().is_err());
+        assert_eq!(manager.covering(Path::new("a/b")).len(), 2);
+        assert!(!manager.is_permitted(Path::new("a/b"), false, &[]));
+        assert!(manager.is_permitted(Path::new("a/b"), false, &[&file.token]));
+        assert!(manager.is_permitted(Path::new("c"), true, &[]));
+        assert!(manager.unlock(&dir.token, Path::new("a/b")).unwrap());
+        assert!(!manager.unlock(&file.token, Path::new("a")).unwrap());
+        manager.remove_under(Path::new("a")).unwrap();
+        assert!(manager.covering(Path::new("a/b")).is_empty());
+        assert_ne!(new_token(), new_token());
+    }
+}
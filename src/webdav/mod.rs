@@ -0,0 +1,1158 @@
+//! The WebDAV class 1 and 2 methods on the files of the opener, the `PROPFIND` works on every
+//! opener, the other methods need the write mode and the local files.
+mod lock;
+mod xml;
+
+use std::{
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use hyper::{
+    body::{Buf, HttpBody},
+    header, Method, Request, Response, StatusCode, Uri,
+};
+use percent_encoding::utf8_percent_encode;
+
+use crate::{
+    body::Body,
+    config::Config,
+    error::{BoxError, Error},
+    executor::BlockingFuture,
+    file::{local_etag, FileReaderOpener, FileWithMeta},
+    listing::HREF_ENCODE_SET,
+    request_resolve::{request_host, request_path, sandbox_path, strip_host},
+    write::{self, status, target_meta, temp_path},
+};
+
+pub(crate) use self::lock::LockManager;
+pub use self::lock::{Lock, LockStore};
+use self::{
+    lock::{IfHeader, State},
+    xml::{Element, DAV_NS},
+};
+
+/// the compliance classes of the `DAV` header.
+pub(crate) const DAV_CLASS: &str = "1, 2";
+/// the methods of the WebDAV, the `PROPFIND` is read only.
+pub(crate) const READ_METHODS: &str = "PROPFIND";
+pub(crate) const WRITE_METHODS: &str = "PROPPATCH, MKCOL, COPY, MOVE, LOCK, UNLOCK";
+
+const MAX_XML_BODY: usize = 64 * 1024;
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+// the live properties of the resource, in the order of the `allprop`.
+const LIVE_PROPS: &[&str] = &[
+    "resourcetype",
+    "displayname",
+    "getcontentlength",
+    "getcontenttype",
+    "getetag",
+    "getlastmodified",
+    "supportedlock",
+    "lockdiscovery",
+];
+const SUPPORTED_LOCK: &str = "<D:lockentry><D:lockscope><D:exclusive/></D:lockscope>\
+    <D:locktype><D:write/></D:locktype></D:lockentry>\
+    <D:lockentry><D:lockscope><D:shared/></D:lockscope>\
+    <D:locktype><D:write/></D:locktype></D:lockentry>";
+
+/// the method is handled by the WebDAV, the `DELETE` removes the collection recursively,
+/// the `PUT` and the `PATCH` check the locks before the write.
+pub(crate) fn is_webdav_method(method: &Method) -> bool {
+    matches!(
+        method.as_str(),
+        "PROPFIND"
+            | "PROPPATCH"
+            | "MKCOL"
+            | "COPY"
+            | "MOVE"
+            | "DELETE"
+            | "LOCK"
+            | "UNLOCK"
+            | "PUT"
+            | "PATCH"
+    )
+}
+
+fn xml_error(status: StatusCode, condition: &str) -> Result<Response<Body>, Error> {
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <D:error xmlns:D=\"DAV:\"><D:{condition}/></D:error>\n"
+    );
+    xml_response(status, xml)
+}
+
+fn xml_response(status: StatusCode, xml: String) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, XML_CONTENT_TYPE)
+        .header(header::CONTENT_LENGTH, xml.len())
+        .body(Body::Bytes(xml.into()))?)
+}
+
+/// read the xml body, none if the body is broken or too large.
+async fn read_body<B: HttpBody>(body: B) -> Option<Vec<u8>> {
+    let mut body = Box::pin(body);
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let mut chunk = chunk.ok()?;
+        if buf.len() + chunk.remaining() > MAX_XML_BODY {
+            return None;
+        }
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            let n = bytes.len();
+            buf.extend_from_slice(bytes);
+            chunk.advance(n);
+        }
+    }
+    Some(buf)
+}
+
+/// parse the xml body, the empty body is none.
+fn parse_body(body: &[u8]) -> Result<Option<Element>, StatusCode> {
+    let body = std::str::from_utf8(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if body.trim().is_empty() {
+        return Ok(None);
+    }
+    xml::parse(body).map(Some).ok_or(StatusCode::BAD_REQUEST)
+}
+
+/// handle the WebDAV request, the methods change the files need the writable opener.
+pub(crate) async fn handle<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+    writable: bool,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let method = head.method().as_str();
+    if method != "PROPFIND" && !writable {
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, config.allow_header(writable))
+            .body(Body::Empty)?);
+    }
+    match method {
+        "PROPFIND" => return propfind(opener, config, head, body).await,
+        "LOCK" => return lock(opener, config, head, body).await,
+        "UNLOCK" => return unlock(opener, config, head).await,
+        "COPY" | "MOVE" => return copy_or_move(opener, config, head).await,
+        _ => {}
+    }
+    let path = request_path(&*opener, &config, head);
+    if let Some(ref path) = path {
+        let deep = method == "DELETE";
+        if let Some(s) = check_write(&opener, &config, head, &[(path, deep)]).await? {
+            return status(s);
+        }
+    }
+    match method {
+        "PROPPATCH" => proppatch(opener, config, head, body).await,
+        "MKCOL" => mkcol(opener, config, head, body).await,
+        "PUT" | "PATCH" => write::put_or_patch(opener, config, head, body).await,
+        _ => {
+            let resp = write::delete(opener.clone(), config.clone(), head, true).await?;
+            if let (StatusCode::NO_CONTENT, Some(path)) = (resp.status(), path) {
+                remove_locks(&*opener, &config, path).await?;
+            }
+            Ok(resp)
+        }
+    }
+}
+
+/// remove the locks of the removed or moved resource.
+async fn remove_locks<O: FileReaderOpener>(
+    opener: &O,
+    config: &Arc<Config>,
+    path: PathBuf,
+) -> Result<(), Error> {
+    let config = config.clone();
+    let executor = opener.blocking_executor();
+    BlockingFuture::spawn(&*executor, move || config.locks.remove_under(&path)).await?;
+    Ok(())
+}
+
+/// evaluate the `If` header and the locks of the changed paths, the deep change includes the
+/// members of the collection. the status is returned if the change is refused.
+async fn check_write<O: FileReaderOpener>(
+    opener: &Arc<O>,
+    config: &Arc<Config>,
+    head: &Request<()>,
+    changed: &[(&Path, bool)],
+) -> Result<Option<StatusCode>, Error> {
+    let if_header = match head.headers().get("if") {
+        Some(v) => match v.to_str().ok().and_then(IfHeader::parse) {
+            Some(if_header) => Some(if_header),
+            None => return Ok(Some(StatusCode::BAD_REQUEST)),
+        },
+        None => None,
+    };
+    let tokens = if_header.as_ref().map(|h| h.tokens()).unwrap_or_default();
+    for (path, deep) in changed {
+        if !config.locks.is_permitted(path, *deep, &tokens) {
+            return Ok(Some(StatusCode::LOCKED));
+        }
+    }
+    let if_header = match if_header {
+        Some(if_header) => if_header,
+        None => return Ok(None),
+    };
+    // the untagged list applies to the request, the tagged list applies to the changed paths.
+    let request = request_path(&**opener, config, head);
+    let lists = if_header
+        .lists
+        .into_iter()
+        .filter_map(|list| {
+            let path = match list.resource {
+                Some(ref uri) => {
+                    let uri = uri.parse::<Uri>().ok()?;
+                    sandbox_path(&**opener, config, head, uri.path())?
+                }
+                None => request.clone()?,
+            };
+            let is_applied = request.as_deref() == Some(&path)
+                || changed.iter().any(|(p, _)| *p == path.as_path());
+            is_applied.then_some((path, list.conditions))
+        })
+        .collect::<Vec<_>>();
+    if lists.is_empty() {
+        return Ok(None);
+    }
+    let (opener, config) = (opener.clone(), config.clone());
+    let executor = opener.blocking_executor();
+    let is_true = BlockingFuture::spawn(&*executor, move || {
+        let is_true = lists.iter().any(|(path, conditions)| {
+            let etag = opener
+                .local_path(path)
+                .and_then(|p| target_meta(&p).ok().flatten())
+                .and_then(|m| local_etag(&m));
+            let locks = config.locks.covering(path);
+            conditions.iter().all(|c| {
+                let holds = match c.state {
+                    State::Token(ref token) => locks.iter().any(|l| l.token == *token),
+                    // the strong comparison as the `If-Match`.
+                    State::ETag(ref tag) => etag.as_deref() == Some(tag.as_str()),
+                };
+                holds != c.not
+            })
+        });
+        Ok(is_true)
+    })
+    .await?;
+    Ok((!is_true).then_some(StatusCode::PRECONDITION_FAILED))
+}
+
+/// the resource of the multistatus response.
+struct Resource {
+    href: String,
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+    etag: Option<String>,
+}
+
+impl Resource {
+    /// the content of the live property, none if it's unknown.
+    fn prop(&self, config: &Config, name: &str) -> Option<String> {
+        match name {
+            "resourcetype" if self.is_dir => Some("<D:collection/>".to_string()),
+            "resourcetype" => Some(String::new()),
+            "displayname" => Some(xml::escape(&self.name)),
+            "getcontentlength" if !self.is_dir => Some(self.size.to_string()),
+            "getcontenttype" if !self.is_dir => {
+                config.mime_type(Path::new(&self.name)).map(xml::escape)
+            }
+            "getetag" => self.etag.as_deref().map(xml::escape),
+            "getlastmodified" => self.modified.map(httpdate::fmt_http_date),
+            "supportedlock" => Some(SUPPORTED_LOCK.to_string()),
+            "lockdiscovery" => Some(
+                config
+                    .locks
+                    .covering(&self.path)
+                    .iter()
+                    .map(|l| l.render())
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+enum PropRequest {
+    AllProp,
+    PropName,
+    Prop(Vec<(String, String)>),
+}
+
+impl PropRequest {
+    fn from_body(root: Option<Element>) -> Result<Self, StatusCode> {
+        let root = match root {
+            Some(root) if root.is_dav("propfind") => root,
+            Some(_) => return Err(StatusCode::BAD_REQUEST),
+            None => return Ok(PropRequest::AllProp),
+        };
+        if root.dav_child("propname").is_some() {
+            return Ok(PropRequest::PropName);
+        }
+        match root.dav_child("prop") {
+            Some(prop) => Ok(PropRequest::Prop(
+                prop.children
+                    .iter()
+                    .map(|c| (c.ns.clone(), c.name.clone()))
+                    .collect(),
+            )),
+            None => Ok(PropRequest::AllProp),
+        }
+    }
+}
+
+/// the empty element of the property, the property outside the `DAV:` declares the namespace.
+fn empty_prop(ns: &str, name: &str) -> String {
+    if ns == DAV_NS {
+        format!("<D:{name}/>")
+    } else {
+        format!("<R:{name} xmlns:R=\"{}\"/>", xml::escape(ns))
+    }
+}
+
+fn propstat(props: &str, status: StatusCode) -> String {
+    format!(
+        "<D:propstat><D:prop>{props}</D:prop><D:status>HTTP/1.1 {status}</D:status></D:propstat>"
+    )
+}
+
+fn multistatus(responses: &[String]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">",
+    );
+    for response in responses {
+        xml.push_str(response);
+    }
+    xml.push_str("</D:multistatus>\n");
+    xml
+}
+
+fn render_response(resource: &Resource, config: &Config, request: &PropRequest) -> String {
+    let mut found = String::new();
+    let mut missing = String::new();
+    match request {
+        PropRequest::PropName => {
+            for name in LIVE_PROPS {
+                found.push_str(&empty_prop(DAV_NS, name));
+            }
+        }
+        PropRequest::AllProp => {
+            for name in LIVE_PROPS {
+                match resource.prop(config, name) {
+                    Some(value) if value.is_empty() => found.push_str(&format!("<D:{name}/>")),
+                    Some(value) => found.push_str(&format!("<D:{name}>{value}</D:{name}>")),
+                    None => {}
+                }
+            }
+        }
+        PropRequest::Prop(props) => {
+            for (ns, name) in props {
+                let value = (ns == DAV_NS)
+                    .then(|| resource.prop(config, name))
+                    .flatten();
+                match value {
+                    Some(value) if value.is_empty() => found.push_str(&format!("<D:{name}/>")),
+                    Some(value) => found.push_str(&format!("<D:{name}>{value}</D:{name}>")),
+                    None => missing.push_str(&empty_prop(ns, name)),
+                }
+            }
+        }
+    }
+    let mut response = format!(
+        "<D:response><D:href>{}</D:href>",
+        xml::escape(&resource.href)
+    );
+    if !found.is_empty() || missing.is_empty() {
+        response.push_str(&propstat(&found, StatusCode::OK));
+    }
+    if !missing.is_empty() {
+        response.push_str(&propstat(&missing, StatusCode::NOT_FOUND));
+    }
+    response.push_str("</D:response>");
+    response
+}
+
+fn open_error(e: io::Error) -> Result<Response<Body>, Error> {
+    match e.kind() {
+        io::ErrorKind::NotFound => status(StatusCode::NOT_FOUND),
+        io::ErrorKind::PermissionDenied => status(StatusCode::FORBIDDEN),
+        _ => Err(e.into()),
+    }
+}
+
+/// list the properties of the resource and the members with the depth 1.
+async fn propfind<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody,
+{
+    let depth = head.headers().get("depth").map(|v| v.as_bytes());
+    let depth = match depth {
+        Some(b"0") => 0,
+        Some(b"1") => 1,
+        // the infinite depth is expensive, the client should walk the collections.
+        None | Some(b"infinity") => {
+            let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                       <D:error xmlns:D=\"DAV:\"><D:propfind-finite-depth/></D:error>\n";
+            return xml_response(StatusCode::FORBIDDEN, xml.to_string());
+        }
+        Some(_) => return status(StatusCode::BAD_REQUEST),
+    };
+    let body = match read_body(body).await {
+        Some(body) => body,
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let request = match parse_body(&body).and_then(PropRequest::from_body) {
+        Ok(request) => request,
+        Err(s) => return status(s),
+    };
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    let file: FileWithMeta = match opener.open(&path).await {
+        Ok(file) => file.into(),
+        Err(e) => return open_error(e),
+    };
+    let mut href = head.uri().path().to_string();
+    if file.is_dir && !href.ends_with('/') {
+        href.push('/');
+    }
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut resources = vec![Resource {
+        href: href.clone(),
+        path: path.clone(),
+        name,
+        is_dir: file.is_dir,
+        size: file.size,
+        modified: file.modified,
+        etag: file.etag.clone(),
+    }];
+    if depth == 1 && file.is_dir {
+        let mut entries = match opener.read_dir(&path).await {
+            Ok(entries) => entries,
+            Err(e) => return open_error(e),
+        };
+        let dir = strip_host(&*opener, &path);
+        entries.retain(|e| !config.is_hidden(&dir.join(&e.name)));
+        entries.truncate(config.max_listing_entries);
+        for entry in entries {
+            let mut href = format!(
+                "{href}{}",
+                utf8_percent_encode(&entry.name, HREF_ENCODE_SET)
+            );
+            if entry.is_dir {
+                href.push('/');
+            }
+            resources.push(Resource {
+                href,
+                path: path.join(&entry.name),
+                name: entry.name,
+                is_dir: entry.is_dir,
+                size: entry.size,
+                modified: entry.modified,
+                etag: None,
+            });
+        }
+    }
+    let responses = resources
+        .iter()
+        .map(|r| render_response(r, &config, &request))
+        .collect::<Vec<_>>();
+    xml_response(StatusCode::MULTI_STATUS, multistatus(&responses))
+}
+
+/// the local path of the request, should be called in the blocking thread.
+fn local_target<O: FileReaderOpener>(opener: &O, path: &Path) -> Result<PathBuf, StatusCode> {
+    opener
+        .local_path(path)
+        .ok_or(StatusCode::METHOD_NOT_ALLOWED)
+}
+
+/// the properties are not stored, every property is reported as updated.
+async fn proppatch<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody,
+{
+    let body = match read_body(body).await {
+        Some(body) => body,
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let root = match parse_body(&body) {
+        Ok(Some(root)) if root.is_dav("propertyupdate") => root,
+        Ok(_) => return status(StatusCode::BAD_REQUEST),
+        Err(s) => return status(s),
+    };
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    let executor = opener.blocking_executor();
+    let exists = BlockingFuture::spawn(&*executor, move || match local_target(&*opener, &path) {
+        Ok(target) => target_meta(&target).map(|m| Ok(m.is_some())),
+        Err(s) => Ok(Err(s)),
+    })
+    .await?;
+    match exists {
+        Ok(true) => {}
+        Ok(false) => return status(StatusCode::NOT_FOUND),
+        Err(s) => return status(s),
+    }
+    let props = root
+        .children
+        .iter()
+        .filter(|c| c.is_dav("set") || c.is_dav("remove"))
+        .filter_map(|c| c.dav_child("prop"))
+        .flat_map(|p| p.children.iter())
+        .map(|p| empty_prop(&p.ns, &p.name))
+        .collect::<String>();
+    let response = format!(
+        "<D:response><D:href>{}</D:href>{}</D:response>",
+        xml::escape(head.uri().path()),
+        propstat(&props, StatusCode::OK)
+    );
+    xml_response(StatusCode::MULTI_STATUS, multistatus(&[response]))
+}
+
+/// create the collection, the parent should exist.
+async fn mkcol<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody,
+{
+    match read_body(body).await {
+        Some(body) if body.is_empty() => {}
+        _ => return status(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+    }
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) if path.file_name().is_some() => path,
+        Some(_) => return status(StatusCode::METHOD_NOT_ALLOWED),
+        None => return status(StatusCode::FORBIDDEN),
+    };
+    let executor = opener.blocking_executor();
+    let s = BlockingFuture::spawn(&*executor, move || {
+        let target = match local_target(&*opener, &path) {
+            Ok(target) => target,
+            Err(s) => return Ok(s),
+        };
+        if target_meta(&target)?.is_some() {
+            return Ok(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        match target.parent().map(target_meta).transpose()?.flatten() {
+            Some(meta) if meta.is_dir() => {}
+            _ => return Ok(StatusCode::CONFLICT),
+        }
+        fs::create_dir(&target)?;
+        Ok(StatusCode::CREATED)
+    })
+    .await?;
+    status(s)
+}
+
+/// the path of the `Destination`, the destination on the other host is not supported.
+fn destination<O: FileReaderOpener>(
+    opener: &O,
+    config: &Config,
+    head: &Request<()>,
+) -> Result<PathBuf, StatusCode> {
+    let destination = head
+        .headers()
+        .get("destination")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<Uri>().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if let Some(authority) = destination.authority() {
+        let host = authority.host().trim_end_matches('.');
+        let is_same = request_host(head)
+            .map(|h| h.eq_ignore_ascii_case(host))
+            .unwrap_or(true);
+        if !is_same {
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    }
+    sandbox_path(opener, config, head, destination.path()).ok_or(StatusCode::FORBIDDEN)
+}
+
+fn remove_all(path: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// remove the partial copy, the link is removed without following it.
+fn remove_temp(path: &Path) {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        let _ = remove_all(path, meta.is_dir());
+    }
+}
+
+/// copy the file or the collection, the members are copied with the infinite depth. the
+/// symbolic link is refused, the link may point out of the root.
+fn copy_all(src: &Path, dst: &Path, deep: bool) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if meta.file_type().is_symlink() {
+        let e = "the symbolic link can't be copied";
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, e));
+    }
+    if !meta.is_dir() {
+        return fs::copy(src, dst).map(|_| ());
+    }
+    fs::create_dir(dst)?;
+    if deep {
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &dst.join(entry.file_name()), deep)?;
+        }
+    }
+    Ok(())
+}
+
+/// copy the resource to the temporary path next to the destination, the partial copy is
+/// removed on the error.
+fn copy_to_temp(src: &Path, dst: &Path, deep: bool) -> io::Result<PathBuf> {
+    let temp = temp_path(dst);
+    match copy_all(src, &temp, deep) {
+        Ok(()) => Ok(temp),
+        Err(e) => {
+            remove_temp(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// place the resource at the destination by the `place`, the existing destination is moved
+/// aside first and it's restored if the `place` fails, so the failed copy don't lose it.
+fn replace_dst<F>(dst: &Path, dst_meta: Option<&Metadata>, place: F) -> io::Result<()>
+where
+    F: FnOnce() -> io::Result<()>,
+{
+    let backup = match dst_meta {
+        Some(_) => {
+            let backup = temp_path(dst);
+            fs::rename(dst, &backup)?;
+            Some(backup)
+        }
+        None => None,
+    };
+    match (place(), backup, dst_meta) {
+        (Ok(()), Some(backup), Some(meta)) => remove_all(&backup, meta.is_dir()),
+        (Ok(()), _, _) => Ok(()),
+        (Err(e), backup, _) => {
+            if let Some(backup) = backup {
+                let _ = fs::rename(&backup, dst);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// copy or move the resource to the `Destination`, should be called in the blocking thread.
+fn copy_or_move_blocking(
+    src: PathBuf,
+    dst: PathBuf,
+    is_move: bool,
+    overwrite: bool,
+    deep: bool,
+) -> io::Result<StatusCode> {
+    let src_meta = match target_meta(&src)? {
+        Some(meta) => meta,
+        None => return Ok(StatusCode::NOT_FOUND),
+    };
+    // the resource can't be copied into itself or its members.
+    if dst.starts_with(&src) || src.starts_with(&dst) {
+        return Ok(StatusCode::FORBIDDEN);
+    }
+    match dst.parent().map(target_meta).transpose()?.flatten() {
+        Some(meta) if meta.is_dir() => {}
+        _ => return Ok(StatusCode::CONFLICT),
+    }
+    let dst_meta = target_meta(&dst)?;
+    if dst_meta.is_some() && !overwrite {
+        return Ok(StatusCode::PRECONDITION_FAILED);
+    }
+    let placed = if is_move {
+        replace_dst(&dst, dst_meta.as_ref(), || match fs::rename(&src, &dst) {
+            Ok(()) => Ok(()),
+            // the mounts may be on the different file systems.
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                let temp = copy_to_temp(&src, &dst, true)?;
+                if let Err(e) = fs::rename(&temp, &dst) {
+                    remove_temp(&temp);
+                    return Err(e);
+                }
+                remove_all(&src, src_meta.is_dir())
+            }
+            Err(e) => Err(e),
+        })
+    } else {
+        // the copy is done before the destination is touched.
+        copy_to_temp(&src, &dst, deep).and_then(|temp| {
+            replace_dst(&dst, dst_meta.as_ref(), || fs::rename(&temp, &dst)).inspect_err(|_| {
+                remove_temp(&temp);
+            })
+        })
+    };
+    match placed {
+        Ok(()) if dst_meta.is_some() => Ok(StatusCode::NO_CONTENT),
+        Ok(()) => Ok(StatusCode::CREATED),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(StatusCode::FORBIDDEN),
+        Err(e) => Err(e),
+    }
+}
+
+async fn copy_or_move<O: FileReaderOpener>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+) -> Result<Response<Body>, Error> {
+    let is_move = head.method().as_str() == "MOVE";
+    let overwrite = match head.headers().get("overwrite").map(|v| v.as_bytes()) {
+        None | Some(b"T") => true,
+        Some(b"F") => false,
+        Some(_) => return status(StatusCode::BAD_REQUEST),
+    };
+    let deep = match head.headers().get("depth").map(|v| v.as_bytes()) {
+        None | Some(b"infinity") => true,
+        Some(b"0") if !is_move => false,
+        Some(_) => return status(StatusCode::BAD_REQUEST),
+    };
+    let src = match request_path(&*opener, &config, head) {
+        Some(path) if path.file_name().is_some() => path,
+        Some(_) => return status(StatusCode::FORBIDDEN),
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    let dst = match destination(&*opener, &config, head) {
+        Ok(path) if path.file_name().is_some() => path,
+        Ok(_) => return status(StatusCode::FORBIDDEN),
+        Err(s) => return status(s),
+    };
+    // the moved source is removed, the copy only changes the destination.
+    let changed = if is_move {
+        vec![(src.as_path(), true), (dst.as_path(), true)]
+    } else {
+        vec![(dst.as_path(), true)]
+    };
+    if let Some(s) = check_write(&opener, &config, head, &changed).await? {
+        return status(s);
+    }
+    let executor = opener.blocking_executor();
+    let moved = src.clone();
+    let o = opener.clone();
+    let s = BlockingFuture::spawn(&*executor, move || {
+        let src = local_target(&*o, &src);
+        let dst = local_target(&*o, &dst);
+        match (src, dst) {
+            (Ok(src), Ok(dst)) => copy_or_move_blocking(src, dst, is_move, overwrite, deep),
+            (Err(s), _) | (_, Err(s)) => Ok(s),
+        }
+    })
+    .await?;
+    if is_move && s.is_success() {
+        remove_locks(&*opener, &config, moved).await?;
+    }
+    status(s)
+}
+
+/// the xml of the lock owner, the href or the text.
+fn lock_owner(owner: &Element) -> Option<String> {
+    match owner.dav_child("href") {
+        Some(href) => Some(format!(
+            "<D:href>{}</D:href>",
+            xml::escape(href.text.trim())
+        )),
+        None => {
+            let text = owner.text.trim();
+            (!text.is_empty()).then(|| xml::escape(text))
+        }
+    }
+}
+
+fn lock_discovery(status: StatusCode, lock: &Lock) -> Result<Response<Body>, Error> {
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <D:prop xmlns:D=\"DAV:\"><D:lockdiscovery>{}</D:lockdiscovery></D:prop>\n",
+        lock.render()
+    );
+    let mut resp = xml_response(status, xml)?;
+    if status == StatusCode::CREATED || status == StatusCode::OK {
+        let token = format!("<{}>", lock.token);
+        resp.headers_mut().insert(
+            "lock-token",
+            token.parse().map_err(hyper::http::Error::from)?,
+        );
+    }
+    Ok(resp)
+}
+
+/// lock the resource, the unmapped url is created as the empty file. the `LOCK` without the
+/// body refreshes the lock of the token in the `If` header.
+async fn lock<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody,
+{
+    let body = match read_body(body).await {
+        Some(body) => body,
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let root = match parse_body(&body) {
+        Ok(root) => root,
+        Err(s) => return status(s),
+    };
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    let timeout = lock::parse_timeout(head.headers().get("timeout").and_then(|v| v.to_str().ok()));
+    let root = match root {
+        Some(root) if root.is_dav("lockinfo") => root,
+        Some(_) => return status(StatusCode::BAD_REQUEST),
+        None => return refresh(opener, config, head, path, timeout).await,
+    };
+    let scope = root.dav_child("lockscope");
+    let exclusive = match scope {
+        Some(scope) if scope.dav_child("exclusive").is_some() => true,
+        Some(scope) if scope.dav_child("shared").is_some() => false,
+        _ => return status(StatusCode::BAD_REQUEST),
+    };
+    if root
+        .dav_child("locktype")
+        .and_then(|t| t.dav_child("write"))
+        .is_none()
+    {
+        return status(StatusCode::BAD_REQUEST);
+    }
+    let deep = match head.headers().get("depth").map(|v| v.as_bytes()) {
+        None | Some(b"infinity") => true,
+        Some(b"0") => false,
+        Some(_) => return status(StatusCode::BAD_REQUEST),
+    };
+    if let Some(s) = check_write(&opener, &config, head, &[]).await? {
+        return status(s);
+    }
+    let lock = Lock {
+        token: lock::new_token(),
+        path: path.clone(),
+        href: head.uri().path().to_string(),
+        exclusive,
+        deep,
+        owner: root.dav_child("owner").and_then(lock_owner),
+        timeout,
+        expires_at: SystemTime::now() + timeout,
+    };
+    let executor = opener.blocking_executor();
+    let locked = BlockingFuture::spawn(&*executor, move || {
+        let target = match local_target(&*opener, &path) {
+            Ok(target) => target,
+            Err(s) => return Ok((s, None)),
+        };
+        let exists = target_meta(&target)?.is_some();
+        if !exists {
+            match target.parent().map(target_meta).transpose()?.flatten() {
+                Some(meta) if meta.is_dir() => {}
+                _ => return Ok((StatusCode::CONFLICT, None)),
+            }
+        }
+        let lock = match config.locks.lock(lock)? {
+            Ok(lock) => lock,
+            Err(_) => return Ok((StatusCode::LOCKED, None)),
+        };
+        if exists {
+            return Ok((StatusCode::OK, Some(lock)));
+        }
+        // the lock of the unmapped url creates the empty resource.
+        if let Err(e) = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&target)
+        {
+            config.locks.unlock(&lock.token, &lock.path)?;
+            return Err(e);
+        }
+        Ok((StatusCode::CREATED, Some(lock)))
+    })
+    .await?;
+    match locked {
+        (s, Some(lock)) => lock_discovery(s, &lock),
+        (StatusCode::LOCKED, None) => xml_error(StatusCode::LOCKED, "no-conflicting-lock"),
+        (s, None) => status(s),
+    }
+}
+
+/// refresh the timeout of the lock submitted in the `If` header.
+async fn refresh<O: FileReaderOpener>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    path: PathBuf,
+    timeout: Duration,
+) -> Result<Response<Body>, Error> {
+    let if_header = head
+        .headers()
+        .get("if")
+        .and_then(|v| v.to_str().ok())
+        .and_then(IfHeader::parse);
+    let tokens = match if_header {
+        Some(if_header) => if_header
+            .tokens()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>(),
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let executor = opener.blocking_executor();
+    let lock = BlockingFuture::spawn(&*executor, move || {
+        for token in tokens {
+            if let Some(lock) = config.locks.refresh(&token, &path, timeout)? {
+                return Ok(Some(lock));
+            }
+        }
+        Ok(None)
+    })
+    .await?;
+    match lock {
+        Some(lock) => {
+            let mut resp = lock_discovery(StatusCode::OK, &lock)?;
+            resp.headers_mut().remove("lock-token");
+            Ok(resp)
+        }
+        None => xml_error(StatusCode::PRECONDITION_FAILED, "lock-token-submitted"),
+    }
+}
+
+/// remove the lock of the `Lock-Token`, the lock should apply to the request.
+async fn unlock<O: FileReaderOpener>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+) -> Result<Response<Body>, Error> {
+    let token = head
+        .headers()
+        .get("lock-token")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        });
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => return status(StatusCode::BAD_REQUEST),
+    };
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    let executor = opener.blocking_executor();
+    let unlocked =
+        BlockingFuture::spawn(&*executor, move || config.locks.unlock(&token, &path)).await?;
+    if unlocked {
+        status(StatusCode::NO_CONTENT)
+    } else {
+        xml_error(StatusCode::CONFLICT, "lock-token-matches-request-uri")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{body_string, call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_webdav() {
+        let root = TempRoot::new("webdav");
+        root.write("docs/a b.txt", "hello");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .webdav(true)
+            .build();
+        runtime().block_on(async {
+            let mut dav =
+                |method: &str, uri: &str, headers: &[(&str, &str)], body: &'static str| {
+                    let mut req = request(method, uri, headers, body);
+                    let host = header::HeaderValue::from_static("localhost");
+                    req.headers_mut().insert(header::HOST, host);
+                    call(&mut svc, req)
+                };
+            let resp = dav("OPTIONS", "/", &[], "").await;
+            assert_eq!(resp.headers()["dav"], "1, 2");
+            let allow = resp.headers()[header::ALLOW].to_str().unwrap();
+            assert!(allow.ends_with("PROPFIND, PROPPATCH, MKCOL, COPY, MOVE, LOCK, UNLOCK"));
+            let resp = dav("PROPFIND", "/docs", &[], "").await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            let resp = dav("PROPFIND", "/docs", &[("depth", "1")], "").await;
+            assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+            let body = body_string(resp).await;
+            assert!(body.contains("<D:href>/docs/</D:href>"));
+            assert!(body.contains("<D:href>/docs/a%20b.txt</D:href>"));
+            assert!(body.contains("<D:getcontentlength>5</D:getcontentlength>"));
+            assert!(body.contains("<D:resourcetype><D:collection/></D:resourcetype>"));
+            let propfind = r#"<?xml version="1.0"?><propfind xmlns="DAV:"><prop>
+                <getcontentlength/><quota-used-bytes/></prop></propfind>"#;
+            let resp = dav("PROPFIND", "/docs/a%20b.txt", &[("depth", "0")], propfind).await;
+            let body = body_string(resp).await;
+            assert!(body.contains("<D:quota-used-bytes/></D:prop><D:status>HTTP/1.1 404"));
+            let resp = dav("MKCOL", "/new", &[], "").await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            let resp = dav("MKCOL", "/new", &[], "").await;
+            assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+            let resp = dav("MKCOL", "/missing/new", &[], "").await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            let dest = [("destination", "http://localhost/new/docs")];
+            let resp = dav("COPY", "/docs", &dest, "").await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert_eq!(root.read("new/docs/a b.txt"), b"hello");
+            let resp = dav("COPY", "/docs", &[dest[0], ("overwrite", "F")], "").await;
+            assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+            let dest = [("destination", "/new/moved.txt")];
+            let resp = dav("MOVE", "/docs/a%20b.txt", &dest, "").await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert!(!root.join("docs/a b.txt").exists());
+            let dest = [("destination", "http://other.example/x.txt")];
+            let resp = dav("MOVE", "/new/moved.txt", &dest, "").await;
+            assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+            let resp = dav("PUT", "/new/c.txt", &[], "c").await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            let resp = dav("DELETE", "/new", &[], "").await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert!(!root.join("new").exists());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_webdav_copy_links() {
+        let root = TempRoot::new("webdav-links");
+        root.write("src/a.txt", "a")
+            .write("dst/b.txt", "b")
+            .write("secret.txt", "secret");
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("src/link.txt")).unwrap();
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .webdav(true)
+            .build();
+        runtime().block_on(async {
+            let mut dav = |method: &str, uri: &str, headers: &[(&str, &str)]| {
+                call(&mut svc, request(method, uri, headers, ""))
+            };
+            // the link is refused and the existing destination is kept.
+            let resp = dav("COPY", "/src", &[("destination", "/dst")]).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            assert_eq!(root.read("dst/b.txt"), b"b");
+            assert_eq!(fs::read_dir(root.path()).unwrap().count(), 3);
+            let resp = dav("COPY", "/src/link.txt", &[("destination", "/c.txt")]).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            assert!(!root.join("c.txt").exists());
+            // the destination is replaced after the copy is done.
+            let resp = dav("COPY", "/src/a.txt", &[("destination", "/dst")]).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(root.read("dst"), b"a");
+            let resp = dav("MOVE", "/dst", &[("destination", "/src/a.txt")]).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(fs::read_dir(root.path()).unwrap().count(), 2);
+        });
+    }
+
+    #[test]
+    fn test_webdav_lock() {
+        let root = TempRoot::new("webdav-lock");
+        root.mkdir("docs");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .webdav(true)
+            .build();
+        runtime().block_on(async {
+            let mut dav = |method: &str, uri: &str, headers: &[(&str, &str)], body: String| {
+                call(&mut svc, request(method, uri, headers, body))
+            };
+            let lockinfo = |scope: &str| {
+                format!(
+                    r#"<?xml version="1.0"?><D:lockinfo xmlns:D="DAV:">
+                    <D:lockscope><D:{scope}/></D:lockscope><D:locktype><D:write/></D:locktype>
+                    <D:owner><D:href>mailto:a@example.com</D:href></D:owner></D:lockinfo>"#
+                )
+            };
+            let timeout = [("timeout", "Second-600")];
+            let resp = dav("LOCK", "/docs/a.txt", &timeout, lockinfo("exclusive")).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            let token = resp.headers()["lock-token"].to_str().unwrap().to_string();
+            let body = body_string(resp).await;
+            assert!(body.contains("<D:owner><D:href>mailto:a@example.com</D:href></D:owner>"));
+            assert!(body.contains("<D:timeout>Second-600<"));
+            let resp = dav("LOCK", "/docs", &[], lockinfo("shared")).await;
+            assert_eq!(resp.status(), StatusCode::LOCKED);
+            let resp = dav("PUT", "/docs/a.txt", &[], "a".into()).await;
+            assert_eq!(resp.status(), StatusCode::LOCKED);
+            let resp = dav("DELETE", "/docs", &[], String::new()).await;
+            assert_eq!(resp.status(), StatusCode::LOCKED);
+            let if_token = format!("({token})");
+            let resp = dav("PUT", "/docs/a.txt", &[("if", &if_token)], "a".into()).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let if_etag = format!("({token} [\"other\"])");
+            let resp = dav("PUT", "/docs/a.txt", &[("if", &if_etag)], "b".into()).await;
+            assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+            let resp = dav("LOCK", "/docs/a.txt", &[("if", &if_token)], String::new()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let depth = [("depth", "0")];
+            let resp = dav("PROPFIND", "/docs/a.txt", &depth, String::new()).await;
+            let body = body_string(resp).await;
+            assert!(body.contains(token.trim_matches(|c| c == '<' || c == '>')));
+            let unlock = [("lock-token", token.as_str())];
+            let resp = dav("UNLOCK", "/docs", &unlock, String::new()).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            let resp = dav("UNLOCK", "/docs/a.txt", &unlock, String::new()).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = dav("DELETE", "/docs", &[], String::new()).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = dav("OPTIONS", "*", &[], String::new()).await;
+            assert_eq!(resp.headers()["dav"], "1, 2");
+        });
+    }
+}
@@ -0,0 +1,228 @@
+//! The minimal xml reader of the WebDAV request bodies, the elements are resolved to the
+//! namespace and the local name, the comments and the entities are ignored.
+use std::collections::HashMap;
+
+const MAX_DEPTH: usize = 32;
+
+pub(crate) const DAV_NS: &str = "DAV:";
+
+/// The element of the xml body.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Element {
+    pub(crate) ns: String,
+    pub(crate) name: String,
+    pub(crate) children: Vec<Element>,
+    // the unescaped text of the element, e.g. the href of the lock owner.
+    pub(crate) text: String,
+}
+
+impl Element {
+    /// the element is the `DAV:` element with the name.
+    pub(crate) fn is_dav(&self, name: &str) -> bool {
+        self.ns == DAV_NS && self.name == name
+    }
+
+    /// the first child of the `DAV:` element with the name.
+    pub(crate) fn dav_child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.is_dav(name))
+    }
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attrs: Vec<(&'a str, String)>,
+    is_empty: bool,
+}
+
+fn parse_tag(tag: &str) -> Option<Tag<'_>> {
+    let (tag, is_empty) = match tag.strip_suffix('/') {
+        Some(tag) => (tag, true),
+        None => (tag, false),
+    };
+    let tag = tag.trim();
+    let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+    let name = &tag[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+    let mut attrs = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest.find('=')?;
+        let attr = rest[..eq].trim();
+        rest = rest[eq + 1..].trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let end = rest[1..].find(quote)? + 1;
+        attrs.push((attr, unescape(&rest[1..end])));
+        rest = rest[end + 1..].trim_start();
+    }
+    Some(Tag {
+        name,
+        attrs,
+        is_empty,
+    })
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// the element with the namespace declarations in scope.
+struct Open {
+    element: Element,
+    // the prefix of the element, resolved when the element is closed.
+    prefix: String,
+    namespaces: HashMap<String, String>,
+}
+
+/// parse the xml body to the root element, none if the xml is invalid.
+pub(crate) fn parse(xml: &str) -> Option<Element> {
+    let mut stack: Vec<Open> = Vec::new();
+    let mut root = None;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        if let Some(open) = stack.last_mut() {
+            open.element.text.push_str(&unescape(&rest[..start]));
+        }
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("<?") {
+            rest = &after[after.find("?>")? + 2..];
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = &after[after.find("-->")? + 3..];
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>")?;
+            if let Some(open) = stack.last_mut() {
+                open.element.text.push_str(&after[..end]);
+            }
+            rest = &after[end + 3..];
+            continue;
+        }
+        // the doctype may declare the entities, it's never accepted.
+        if rest.starts_with("<!") {
+            return None;
+        }
+        let end = rest.find('>')?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let open = stack.pop()?;
+            let qname = match open.prefix.as_str() {
+                "" => open.element.name.clone(),
+                prefix => format!("{prefix}:{}", open.element.name),
+            };
+            if qname != name.trim() {
+                return None;
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.element.children.push(open.element),
+                None => root = Some(open.element),
+            }
+            continue;
+        }
+        if root.is_some() || stack.len() >= MAX_DEPTH {
+            return None;
+        }
+        let tag = parse_tag(tag)?;
+        let mut namespaces = stack
+            .last()
+            .map(|o| o.namespaces.clone())
+            .unwrap_or_default();
+        for (attr, value) in tag.attrs.iter() {
+            if *attr == "xmlns" {
+                namespaces.insert(String::new(), value.clone());
+            } else if let Some(prefix) = attr.strip_prefix("xmlns:") {
+                namespaces.insert(prefix.to_string(), value.clone());
+            }
+        }
+        let (prefix, name) = match tag.name.split_once(':') {
+            Some((prefix, name)) => (prefix, name),
+            None => ("", tag.name),
+        };
+        let ns = match namespaces.get(prefix) {
+            Some(ns) => ns.clone(),
+            None if prefix.is_empty() => String::new(),
+            None => return None,
+        };
+        let element = Element {
+            ns,
+            name: name.to_string(),
+            ..Default::default()
+        };
+        if tag.is_empty {
+            match stack.last_mut() {
+                Some(parent) => parent.element.children.push(element),
+                None => root = Some(element),
+            }
+        } else {
+            stack.push(Open {
+                element,
+                prefix: prefix.to_string(),
+                namespaces,
+            });
+        }
+    }
+    if !stack.is_empty() {
+        return None;
+    }
+    root
+}
+
+/// escape the text of the xml.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8" ?>
+            <!-- the props -->
+            <D:propfind xmlns:D="DAV:" xmlns:z="urn:x">
+              <D:prop><D:getetag/><z:color/><displayname xmlns="DAV:"></displayname></D:prop>
+            </D:propfind>"#;
+        let root = parse(xml).unwrap();
+        assert!(root.is_dav("propfind"));
+        let prop = root.dav_child("prop").unwrap();
+        let names = prop
+            .children
+            .iter()
+            .map(|c| (c.ns.as_str(), c.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("DAV:", "getetag"),
+                ("urn:x", "color"),
+                ("DAV:", "displayname")
+            ]
+        );
+        let owner = parse("<owner xmlns='DAV:'><href> mailto:a&amp;b </href></owner>").unwrap();
+        assert_eq!(owner.dav_child("href").unwrap().text.trim(), "mailto:a&b");
+        assert!(parse("<a><b></a>").is_none());
+        assert!(parse("<x:a/>").is_none());
+        assert!(parse("<!DOCTYPE a [<!ENTITY x \"y\">]><a/>").is_none());
+    }
+}
@@ -0,0 +1,792 @@
+//! The write methods of the local files, the file is written to the temporary file in the same
+//! directory and renamed to the target, the reader never see the partial file.
+use std::{
+    fs::{self, File, Metadata, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    body::{Buf, HttpBody},
+    header, Method, Request, Response, StatusCode,
+};
+
+use crate::{
+    body::Body,
+    config::Config,
+    error::{BoxError, Error},
+    executor::{BlockingFuture, SharedExecutor},
+    file::{local_etag, FileReaderOpener},
+    range::ContentRange,
+    request_resolve::request_path,
+};
+
+// write the body to the file when the buffer is full.
+const WRITE_BUF_SIZE: usize = 64 * 1024;
+const TEMP_SUFFIX: &str = ".upload";
+// the file of the range upload is extended to the complete length, the length is limited by
+// the `max_upload_size` or by this default.
+const DEFAULT_MAX_COMPLETE_LENGTH: u64 = 64 * 1024 * 1024 * 1024;
+/// the content type of the `PATCH`, the range is in the `Content-Range`.
+pub(crate) const BYTERANGES: &str = "application/byteranges";
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn status(status: StatusCode) -> Result<Response<Body>, Error> {
+    Ok(Response::builder().status(status).body(Body::Empty)?)
+}
+
+/// the `PATCH` request writes the range of the file.
+pub(crate) fn is_byteranges<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().eq_ignore_ascii_case(BYTERANGES))
+        .unwrap_or(false)
+}
+
+/// the conditional headers of the write methods.
+#[derive(Debug, Default)]
+pub(crate) struct Preconditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    if_unmodified_since: Option<SystemTime>,
+}
+
+impl Preconditions {
+    pub(crate) fn from_request<B>(request: &Request<B>) -> Self {
+        let headers = request.headers();
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &header::HeaderValue| v.to_str().ok())
+                .map(String::from)
+        };
+        Self {
+            if_match: value(header::IF_MATCH),
+            if_none_match: value(header::IF_NONE_MATCH),
+            if_unmodified_since: value(header::IF_UNMODIFIED_SINCE)
+                .and_then(|v| httpdate::parse_http_date(&v).ok()),
+        }
+    }
+
+    fn tags(value: &str) -> impl Iterator<Item = &str> {
+        value.split(',').map(str::trim).filter(|t| !t.is_empty())
+    }
+
+    /// evaluate the preconditions with the meta of the current file, none if not exists.
+    pub(crate) fn check(&self, meta: Option<&Metadata>) -> bool {
+        let etag = meta.and_then(local_etag);
+        if let Some(ref if_match) = self.if_match {
+            // the strong comparison, the weak tag never matches.
+            let is_match = match (meta, etag.as_deref()) {
+                (None, _) => false,
+                (Some(_), etag) => Self::tags(if_match).any(|t| t == "*" || Some(t) == etag),
+            };
+            if !is_match {
+                return false;
+            }
+        } else if let (Some(since), Some(meta)) = (self.if_unmodified_since, meta) {
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok());
+            let since = since.duration_since(UNIX_EPOCH).ok();
+            if let (Some(modified), Some(since)) = (modified, since) {
+                if modified.as_secs() > since.as_secs() {
+                    return false;
+                }
+            }
+        }
+        if let (Some(ref if_none_match), Some(_)) = (&self.if_none_match, meta) {
+            let weak = |t: &str| t.strip_prefix("W/").unwrap_or(t).to_string();
+            let etag = etag.as_deref().map(weak);
+            if Self::tags(if_none_match).any(|t| t == "*" || Some(weak(t)) == etag) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// the metadata of the target without following the missing file.
+pub(crate) fn target_meta(path: &Path) -> io::Result<Option<Metadata>> {
+    match fs::metadata(path) {
+        Ok(meta) => Ok(Some(meta)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// the unused temporary path in the directory of the target, the name starts with the `.`.
+pub(crate) fn temp_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("file");
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp_name = format!(
+        ".{name}.{:x}{:x}{:x}{TEMP_SUFFIX}",
+        std::process::id(),
+        nanos,
+        counter
+    );
+    target.with_file_name(temp_name)
+}
+
+/// create the temporary file in the directory of the target, the name starts with the `.`.
+pub(crate) fn create_temp(target: &Path) -> io::Result<(PathBuf, File)> {
+    let mut last_err = io::Error::from(io::ErrorKind::AlreadyExists);
+    for _ in 0..8 {
+        let temp = temp_path(target);
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => last_err = e,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_err)
+}
+
+enum Prepared {
+    Status(StatusCode),
+    Ready {
+        target: PathBuf,
+        temp: PathBuf,
+        file: File,
+    },
+}
+
+enum Finished {
+    Status(StatusCode),
+    Done { etag: Option<String>, created: bool },
+}
+
+/// check the target and create the temporary file, should be called in the blocking thread.
+fn prepare_blocking(
+    target: Option<PathBuf>,
+    preconditions: &Preconditions,
+    create_dirs: bool,
+) -> io::Result<Prepared> {
+    let target = match target {
+        Some(target) => target,
+        None => return Ok(Prepared::Status(StatusCode::METHOD_NOT_ALLOWED)),
+    };
+    let meta = target_meta(&target)?;
+    if meta.as_ref().map(|m| m.is_dir()).unwrap_or(false) {
+        return Ok(Prepared::Status(StatusCode::CONFLICT));
+    }
+    if !preconditions.check(meta.as_ref()) {
+        return Ok(Prepared::Status(StatusCode::PRECONDITION_FAILED));
+    }
+    let parent = match target.parent() {
+        Some(parent) => parent,
+        None => return Ok(Prepared::Status(StatusCode::CONFLICT)),
+    };
+    match target_meta(parent)? {
+        Some(meta) if meta.is_dir() => {}
+        Some(_) => return Ok(Prepared::Status(StatusCode::CONFLICT)),
+        None if create_dirs => fs::create_dir_all(parent)?,
+        None => return Ok(Prepared::Status(StatusCode::CONFLICT)),
+    }
+    let (temp, file) = create_temp(&target)?;
+    Ok(Prepared::Ready { target, temp, file })
+}
+
+/// flush the temporary file and rename it to the target, should be called in the blocking thread.
+fn finish_blocking(
+    mut file: File,
+    buf: Vec<u8>,
+    temp: &Path,
+    target: &Path,
+    preconditions: &Preconditions,
+) -> io::Result<Finished> {
+    file.write_all(&buf)?;
+    file.sync_all()?;
+    drop(file);
+    // the target maybe changed during the upload, check again.
+    let meta = target_meta(target)?;
+    if meta.as_ref().map(|m| m.is_dir()).unwrap_or(false) {
+        let _ = fs::remove_file(temp);
+        return Ok(Finished::Status(StatusCode::CONFLICT));
+    }
+    if !preconditions.check(meta.as_ref()) {
+        let _ = fs::remove_file(temp);
+        return Ok(Finished::Status(StatusCode::PRECONDITION_FAILED));
+    }
+    fs::rename(temp, target)?;
+    let etag = fs::metadata(target).ok().as_ref().and_then(local_etag);
+    Ok(Finished::Done {
+        etag,
+        created: meta.is_none(),
+    })
+}
+
+fn remove_temp(executor: &SharedExecutor, temp: PathBuf) -> BlockingFuture<()> {
+    BlockingFuture::spawn(&**executor, move || {
+        let _ = fs::remove_file(temp);
+        Ok(())
+    })
+}
+
+pub(crate) enum Streamed {
+    Status(StatusCode),
+    // the rest of the buffer is not written, the body is incomplete when it's broken,
+    // e.g. the connection is closed.
+    Done {
+        file: File,
+        buf: Vec<u8>,
+        written: u64,
+        complete: bool,
+    },
+}
+
+/// stream the body to the file in the blocking thread, the body over the limit is responded
+/// with the status.
+pub(crate) async fn stream_body<B>(
+    executor: &SharedExecutor,
+    mut file: File,
+    body: B,
+    limit: Option<u64>,
+    over_limit: StatusCode,
+) -> io::Result<Streamed>
+where
+    B: HttpBody,
+{
+    let mut body = Box::pin(body);
+    let mut buf = Vec::with_capacity(WRITE_BUF_SIZE);
+    let mut written = 0u64;
+    // the error of the body is dropped before the await, it's maybe not `Send`.
+    while let Some(chunk) = body.data().await.map(Result::ok) {
+        let mut chunk = match chunk {
+            Some(chunk) => chunk,
+            None => {
+                return Ok(Streamed::Done {
+                    file,
+                    buf,
+                    written,
+                    complete: false,
+                })
+            }
+        };
+        written += chunk.remaining() as u64;
+        if limit.map(|m| written > m).unwrap_or(false) {
+            return Ok(Streamed::Status(over_limit));
+        }
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            let n = bytes.len();
+            buf.extend_from_slice(bytes);
+            chunk.advance(n);
+        }
+        if buf.len() >= WRITE_BUF_SIZE {
+            let data = std::mem::replace(&mut buf, Vec::with_capacity(WRITE_BUF_SIZE));
+            file = BlockingFuture::spawn(&**executor, move || {
+                file.write_all(&data)?;
+                Ok(file)
+            })
+            .await?;
+        }
+    }
+    Ok(Streamed::Done {
+        file,
+        buf,
+        written,
+        complete: true,
+    })
+}
+
+/// write the body to the file, the file is replaced atomically.
+pub(crate) async fn put<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    if path.file_name().is_none() {
+        return status(StatusCode::CONFLICT);
+    }
+    let executor = opener.blocking_executor();
+    let preconditions = Arc::new(Preconditions::from_request(head));
+    let create_dirs = config.create_dirs;
+    let prepared = {
+        let opener = opener.clone();
+        let preconditions = preconditions.clone();
+        BlockingFuture::spawn(&*executor, move || {
+            prepare_blocking(opener.local_path(&path), &preconditions, create_dirs)
+        })
+        .await?
+    };
+    let (target, temp, file) = match prepared {
+        Prepared::Status(s) => return status(s),
+        Prepared::Ready { target, temp, file } => (target, temp, file),
+    };
+    let limit = config.max_upload_size;
+    let streamed = stream_body(&executor, file, body, limit, StatusCode::PAYLOAD_TOO_LARGE).await;
+    let (file, buf) = match streamed {
+        Ok(Streamed::Done {
+            file,
+            buf,
+            complete: true,
+            ..
+        }) => (file, buf),
+        Ok(Streamed::Done { .. }) => {
+            remove_temp(&executor, temp).await?;
+            return status(StatusCode::BAD_REQUEST);
+        }
+        Ok(Streamed::Status(s)) => {
+            remove_temp(&executor, temp).await?;
+            return status(s);
+        }
+        Err(e) => {
+            remove_temp(&executor, temp).await?;
+            return Err(e.into());
+        }
+    };
+    let finished = {
+        let temp = temp.clone();
+        BlockingFuture::spawn(&*executor, move || {
+            finish_blocking(file, buf, &temp, &target, &preconditions)
+        })
+        .await
+    };
+    let (etag, created) = match finished {
+        Ok(Finished::Done { etag, created }) => (etag, created),
+        Ok(Finished::Status(s)) => return status(s),
+        Err(e) => {
+            remove_temp(&executor, temp).await?;
+            return Err(e.into());
+        }
+    };
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::NO_CONTENT
+    };
+    let mut builder = Response::builder().status(status);
+    if let Some(etag) = etag {
+        builder = builder.header(header::ETAG, etag);
+    }
+    Ok(builder.body(Body::Empty)?)
+}
+
+/// open the target for the partial write and seek to the start, should be called in the
+/// blocking thread. the file is created when it's missing, the other ranges maybe written
+/// concurrently.
+fn open_range_blocking(
+    target: Option<PathBuf>,
+    preconditions: &Preconditions,
+    create_dirs: bool,
+    range: &ContentRange,
+) -> io::Result<Result<(File, bool), StatusCode>> {
+    let target = match target {
+        Some(target) => target,
+        None => return Ok(Err(StatusCode::METHOD_NOT_ALLOWED)),
+    };
+    // the range is written in place, the link may point out of the root.
+    match fs::symlink_metadata(&target) {
+        Ok(meta) if meta.file_type().is_symlink() => return Ok(Err(StatusCode::FORBIDDEN)),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let meta = target_meta(&target)?;
+    if let Some(ref meta) = meta {
+        if meta.is_dir() {
+            return Ok(Err(StatusCode::CONFLICT));
+        }
+        if range
+            .complete_length
+            .map(|l| meta.len() > l)
+            .unwrap_or(false)
+        {
+            return Ok(Err(StatusCode::RANGE_NOT_SATISFIABLE));
+        }
+    }
+    if !preconditions.check(meta.as_ref()) {
+        return Ok(Err(StatusCode::PRECONDITION_FAILED));
+    }
+    if let Some(parent) = target.parent() {
+        if target_meta(parent)?.is_none() {
+            if !create_dirs {
+                return Ok(Err(StatusCode::CONFLICT));
+            }
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(false);
+    // the link is created after the check.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NOFOLLOW);
+    let mut file = match options.open(&target) {
+        Ok(file) => file,
+        #[cfg(unix)]
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => return Ok(Err(StatusCode::FORBIDDEN)),
+        Err(e) => return Err(e),
+    };
+    file.seek(SeekFrom::Start(range.start))?;
+    Ok(Ok((file, meta.is_none())))
+}
+
+/// write the body to the range of the file, the file is extended to the complete length.
+pub(crate) async fn put_range<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let range = head
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(ContentRange::parse);
+    let range = match range {
+        Some(Ok(range)) => range,
+        _ => return status(StatusCode::BAD_REQUEST),
+    };
+    let end = match range.start.checked_add(range.length) {
+        Some(end) => end,
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let size = range.complete_length.unwrap_or(end);
+    if size
+        > config
+            .max_upload_size
+            .unwrap_or(DEFAULT_MAX_COMPLETE_LENGTH)
+    {
+        return status(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    if path.file_name().is_none() {
+        return status(StatusCode::CONFLICT);
+    }
+    let executor = opener.blocking_executor();
+    let preconditions = Preconditions::from_request(head);
+    let create_dirs = config.create_dirs;
+    let length = range.length;
+    let complete_length = range.complete_length;
+    let opened = BlockingFuture::spawn(&*executor, move || {
+        open_range_blocking(
+            opener.local_path(&path),
+            &preconditions,
+            create_dirs,
+            &range,
+        )
+    })
+    .await?;
+    let (file, created) = match opened {
+        Ok(opened) => opened,
+        Err(s) => return status(s),
+    };
+    let streamed = stream_body(&executor, file, body, Some(length), StatusCode::BAD_REQUEST);
+    let (mut file, buf) = match streamed.await? {
+        Streamed::Done {
+            file,
+            buf,
+            written,
+            complete: true,
+        } if written == length => (file, buf),
+        // the received part of the range is kept, the client retries the rest.
+        Streamed::Done {
+            mut file,
+            buf,
+            complete: false,
+            ..
+        } => {
+            BlockingFuture::spawn(&*executor, move || file.write_all(&buf)).await?;
+            return status(StatusCode::BAD_REQUEST);
+        }
+        Streamed::Done { .. } => return status(StatusCode::BAD_REQUEST),
+        Streamed::Status(s) => return status(s),
+    };
+    let etag = BlockingFuture::spawn(&*executor, move || {
+        file.write_all(&buf)?;
+        if let Some(complete_length) = complete_length {
+            if file.metadata()?.len() < complete_length {
+                file.set_len(complete_length)?;
+            }
+        }
+        file.sync_all()?;
+        Ok(file.metadata().ok().as_ref().and_then(local_etag))
+    })
+    .await?;
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::NO_CONTENT
+    };
+    let mut builder = Response::builder().status(status);
+    if let Some(etag) = etag {
+        builder = builder.header(header::ETAG, etag);
+    }
+    Ok(builder.body(Body::Empty)?)
+}
+
+/// write the file by the `PUT` or the `PATCH`, the `Content-Range` writes the range of the file.
+pub(crate) async fn put_or_patch<O, B>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    body: B,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let is_range = head.headers().contains_key(header::CONTENT_RANGE);
+    match *head.method() {
+        Method::PUT if is_range => put_range(opener, config, head, body).await,
+        Method::PUT => put(opener, config, head, body).await,
+        _ if !is_byteranges(head) => Ok(Response::builder()
+            .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            .header("accept-patch", BYTERANGES)
+            .body(Body::Empty)?),
+        _ => put_range(opener, config, head, body).await,
+    }
+}
+
+/// remove the file or the directory, should be called in the blocking thread.
+fn delete_blocking(
+    target: Option<PathBuf>,
+    preconditions: &Preconditions,
+    delete_dirs: bool,
+    recursive: bool,
+) -> io::Result<StatusCode> {
+    let target = match target {
+        Some(target) => target,
+        None => return Ok(StatusCode::METHOD_NOT_ALLOWED),
+    };
+    let meta = match target_meta(&target)? {
+        Some(meta) => meta,
+        None => return Ok(StatusCode::NOT_FOUND),
+    };
+    if !preconditions.check(Some(&meta)) {
+        return Ok(StatusCode::PRECONDITION_FAILED);
+    }
+    if meta.is_dir() && recursive {
+        fs::remove_dir_all(&target)?;
+    } else if meta.is_dir() {
+        if !delete_dirs || fs::read_dir(&target)?.next().is_some() {
+            return Ok(StatusCode::CONFLICT);
+        }
+        fs::remove_dir(&target)?;
+    } else {
+        fs::remove_file(&target)?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// remove the file, the directory is removed only when it's empty and enabled,
+/// the `recursive` removes the directory with the members, e.g. the WebDAV collection.
+pub(crate) async fn delete<O>(
+    opener: Arc<O>,
+    config: Arc<Config>,
+    head: &Request<()>,
+    recursive: bool,
+) -> Result<Response<Body>, Error>
+where
+    O: FileReaderOpener,
+{
+    let path = match request_path(&*opener, &config, head) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+    // the root is never removed.
+    if path.file_name().is_none() {
+        return status(StatusCode::CONFLICT);
+    }
+    let executor = opener.blocking_executor();
+    let preconditions = Preconditions::from_request(head);
+    let delete_dirs = config.delete_dirs;
+    let s = BlockingFuture::spawn(&*executor, move || {
+        delete_blocking(
+            opener.local_path(&path),
+            &preconditions,
+            delete_dirs,
+            recursive,
+        )
+    })
+    .await?;
+    status(s)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fixture::{call, request, runtime, TempRoot},
+        FileServiceBuilder,
+    };
+
+    #[test]
+    fn test_preconditions() {
+        let root = TempRoot::new("precond");
+        root.write("a.txt", "a");
+        let path = root.join("a.txt");
+        let meta = fs::metadata(&path).unwrap();
+        let etag = local_etag(&meta).unwrap();
+        let cond = |if_match: Option<&str>, if_none_match: Option<&str>| Preconditions {
+            if_match: if_match.map(String::from),
+            if_none_match: if_none_match.map(String::from),
+            if_unmodified_since: None,
+        };
+        assert!(etag.starts_with('"'));
+        assert!(cond(Some(&etag), None).check(Some(&meta)));
+        assert!(!cond(Some(&format!("W/{etag}")), None).check(Some(&meta)));
+        assert!(!cond(Some("\"other\""), None).check(Some(&meta)));
+        assert!(!cond(Some("*"), None).check(None));
+        assert!(cond(None, Some("*")).check(None));
+        assert!(!cond(None, Some("*")).check(Some(&meta)));
+        let since = Preconditions {
+            if_unmodified_since: Some(UNIX_EPOCH),
+            ..Default::default()
+        };
+        assert!(!since.check(Some(&meta)));
+    }
+
+    #[test]
+    fn test_put() {
+        let root = TempRoot::new("put");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .max_upload_size(16)
+            .build();
+        runtime().block_on(async {
+            let mut put = |uri: &str, body: &'static str, headers: &[(&str, &str)]| {
+                call(&mut svc, request("PUT", uri, headers, body))
+            };
+            let resp = put("/a.txt", "hello", &[]).await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            let etag = resp.headers()[header::ETAG].to_str().unwrap().to_string();
+            assert!(!etag.starts_with("W/"));
+            let resp = put("/a.txt", "world", &[("if-match", &etag)]).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = put("/a.txt", "again", &[("if-none-match", "*")]).await;
+            assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+            let resp = put("/dir/b.txt", "b", &[]).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            let resp = put("/c.txt", "the body is too large", &[]).await;
+            assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        });
+        assert_eq!(root.read("a.txt"), b"world");
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_delete() {
+        let root = TempRoot::new("delete");
+        root.mkdir("empty")
+            .write("a.txt", "a")
+            .write("full/b.txt", "b");
+        let mut svc = FileServiceBuilder::new(root.root())
+            .write(true)
+            .delete_dirs(true)
+            .build();
+        runtime().block_on(async {
+            let mut delete = |uri: &str, headers: &[(&str, &str)]| {
+                call(&mut svc, request("DELETE", uri, headers, ""))
+            };
+            let resp = delete("/a.txt", &[("if-match", "\"other\"")]).await;
+            assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+            let resp = delete("/a.txt", &[]).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = delete("/a.txt", &[]).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let resp = delete("/full", &[]).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+            let resp = delete("/empty/", &[]).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = call(&mut svc, request("OPTIONS", "*", &[], "")).await;
+            assert_eq!(
+                resp.headers()[header::ALLOW],
+                "GET, HEAD, OPTIONS, PUT, PATCH, DELETE"
+            );
+        });
+        assert!(!root.join("empty").exists());
+    }
+
+    #[test]
+    fn test_put_range() {
+        let root = TempRoot::new("range-put");
+        let mut svc = FileServiceBuilder::new(root.root()).write(true).build();
+        runtime().block_on(async {
+            let mut write = |method: &str, range: &str, body: &'static str| {
+                let headers = [
+                    ("content-range", range),
+                    ("content-type", "application/byteranges"),
+                ];
+                call(&mut svc, request(method, "/snapshot.bin", &headers, body))
+            };
+            let resp = write("PUT", "bytes 5-9/10", "world").await;
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            let resp = write("PATCH", "bytes 0-4/10", "hello").await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = write("PUT", "bytes 0-4/10", "short").await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let resp = write("PUT", "bytes 0-4/10", "hi").await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let resp = write("PUT", "bytes 0-4/4", "hello").await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let resp = write("PUT", "bytes 1-18446744073709551615/*", "hello").await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let resp = write(
+                "PUT",
+                "bytes 18446744073709551610-18446744073709551614/*",
+                "hello",
+            );
+            assert_eq!(resp.await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            // the complete length is limited without the max upload size.
+            let resp = write("PUT", "bytes 0-4/18446744073709551615", "hello").await;
+            assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        });
+        assert_eq!(root.read("snapshot.bin"), b"shortworld");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_put_range_link() {
+        let outside = TempRoot::new("range-outside");
+        outside.write("secret.bin", "secret");
+        let root = TempRoot::new("range-link");
+        std::os::unix::fs::symlink(outside.join("secret.bin"), root.join("link.bin")).unwrap();
+        let mut svc = FileServiceBuilder::new(root.root()).write(true).build();
+        runtime().block_on(async {
+            let headers = [("content-range", "bytes 0-4/6")];
+            let resp = call(&mut svc, request("PUT", "/link.bin", &headers, "hello")).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        });
+        assert_eq!(outside.read("secret.bin"), b"secret");
+    }
+}